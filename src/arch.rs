@@ -0,0 +1,194 @@
+//! Validation of the target architecture and of the binaries packaged for it.
+
+use crate::{AppError, FileEntry, FileKind};
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// The architecture of packages that contain no machine code.
+pub const NOARCH: &str = "noarch";
+
+const EM_SPARC: u16 = 2;
+const EM_386: u16 = 3;
+const EM_68K: u16 = 4;
+const EM_MIPS: u16 = 8;
+const EM_SPARC32PLUS: u16 = 18;
+const EM_PPC: u16 = 20;
+const EM_PPC64: u16 = 21;
+const EM_S390: u16 = 22;
+const EM_ARM: u16 = 40;
+const EM_SH: u16 = 42;
+const EM_SPARCV9: u16 = 43;
+const EM_IA_64: u16 = 50;
+const EM_X86_64: u16 = 62;
+const EM_AARCH64: u16 = 183;
+const EM_RISCV: u16 = 243;
+const EM_LOONGARCH: u16 = 258;
+const EM_ALPHA: u16 = 0x9026;
+
+/// Width and byte order of the binaries of an architecture.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Abi {
+    Le32,
+    Be32,
+    Le64,
+    Be64,
+}
+
+/// The architectures rpm knows, with the machine type and abi of their ELF binaries.
+const ARCHES: &[(&str, &[u16], Abi)] = &[
+    ("i386", &[EM_386], Abi::Le32),
+    ("i486", &[EM_386], Abi::Le32),
+    ("i586", &[EM_386], Abi::Le32),
+    ("i686", &[EM_386], Abi::Le32),
+    ("athlon", &[EM_386], Abi::Le32),
+    ("geode", &[EM_386], Abi::Le32),
+    ("pentium3", &[EM_386], Abi::Le32),
+    ("pentium4", &[EM_386], Abi::Le32),
+    ("x86_64", &[EM_X86_64], Abi::Le64),
+    ("x86_64_v2", &[EM_X86_64], Abi::Le64),
+    ("x86_64_v3", &[EM_X86_64], Abi::Le64),
+    ("x86_64_v4", &[EM_X86_64], Abi::Le64),
+    ("amd64", &[EM_X86_64], Abi::Le64),
+    ("ia32e", &[EM_X86_64], Abi::Le64),
+    ("em64t", &[EM_X86_64], Abi::Le64),
+    ("ia64", &[EM_IA_64], Abi::Le64),
+    ("aarch64", &[EM_AARCH64], Abi::Le64),
+    ("armv3l", &[EM_ARM], Abi::Le32),
+    ("armv4b", &[EM_ARM], Abi::Be32),
+    ("armv4l", &[EM_ARM], Abi::Le32),
+    ("armv5tel", &[EM_ARM], Abi::Le32),
+    ("armv5tejl", &[EM_ARM], Abi::Le32),
+    ("armv6l", &[EM_ARM], Abi::Le32),
+    ("armv6hl", &[EM_ARM], Abi::Le32),
+    ("armv7l", &[EM_ARM], Abi::Le32),
+    ("armv7hl", &[EM_ARM], Abi::Le32),
+    ("armv7hnl", &[EM_ARM], Abi::Le32),
+    ("armv8l", &[EM_ARM], Abi::Le32),
+    ("armv8hl", &[EM_ARM], Abi::Le32),
+    ("ppc", &[EM_PPC], Abi::Be32),
+    ("ppc64", &[EM_PPC64], Abi::Be64),
+    ("ppc64p7", &[EM_PPC64], Abi::Be64),
+    ("ppc64le", &[EM_PPC64], Abi::Le64),
+    ("s390", &[EM_S390], Abi::Be32),
+    ("s390x", &[EM_S390], Abi::Be64),
+    ("riscv64", &[EM_RISCV], Abi::Le64),
+    ("loongarch64", &[EM_LOONGARCH], Abi::Le64),
+    ("mips", &[EM_MIPS], Abi::Be32),
+    ("mipsel", &[EM_MIPS], Abi::Le32),
+    ("mips64", &[EM_MIPS], Abi::Be64),
+    ("mips64el", &[EM_MIPS], Abi::Le64),
+    ("sparc", &[EM_SPARC], Abi::Be32),
+    ("sparcv8", &[EM_SPARC], Abi::Be32),
+    ("sparcv9", &[EM_SPARC, EM_SPARC32PLUS], Abi::Be32),
+    ("sparc64", &[EM_SPARCV9], Abi::Be64),
+    ("alpha", &[EM_ALPHA], Abi::Le64),
+    ("alphaev6", &[EM_ALPHA], Abi::Le64),
+    ("sh4", &[EM_SH], Abi::Le32),
+    ("m68k", &[EM_68K], Abi::Be32),
+];
+
+/// Check that `arch` is an architecture rpm knows.
+pub fn validate(arch: &str) -> Result<(), AppError> {
+    if arch == NOARCH || ARCHES.iter().any(|(name, _, _)| *name == arch) {
+        return Ok(());
+    }
+    let normalized = arch.to_lowercase().replace('-', "_");
+    match ARCHES.iter().find(|(name, _, _)| *name == normalized) {
+        Some((name, _, _)) => Err(app_err!(
+            "unknown architecture {}, did you mean {}?",
+            arch,
+            name
+        )),
+        None => Err(app_err!(
+            "unknown architecture {}, expected noarch or one of {}",
+            arch,
+            ARCHES
+                .iter()
+                .map(|(name, _, _)| *name)
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+/// Check the ELF binaries among `files` against `arch`. Executables built for another machine
+/// are an error, any binary in a noarch package is warned about.
+pub fn check_files(arch: &str, files: &[FileEntry]) -> Result<(), AppError> {
+    if arch == NOARCH {
+        for file in files
+            .iter()
+            .filter(|file| !matches!(file.kind, FileKind::Symlink))
+        {
+            if elf_header(&file.source)?.is_some() {
+                eprintln!(
+                    "warning: {} is an ELF binary, but the package is {}",
+                    file.source.display(),
+                    NOARCH
+                );
+            }
+        }
+        return Ok(());
+    }
+    let (_, machines, abi) = ARCHES.iter().find(|(name, _, _)| *name == arch).unwrap();
+    for file in files
+        .iter()
+        .filter(|file| matches!(file.kind, FileKind::Executable))
+    {
+        if let Some((machine, file_abi)) = elf_header(&file.source)? {
+            if !machines.contains(&machine) || file_abi != *abi {
+                return Err(app_err!(
+                    "{} is an ELF binary for {}, which does not match the architecture {}",
+                    file.source.display(),
+                    describe(machine, file_abi),
+                    arch
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The machine type and abi of an ELF binary, `None` if `path` is no ELF file.
+fn elf_header(path: &Path) -> Result<Option<(u16, Abi)>, AppError> {
+    let mut ident = [0; 20];
+    let read = File::open(path)
+        .and_then(|mut file| read_full(&mut file, &mut ident))
+        .map_err(|e| app_err!("error reading {}: {}", path.display(), e))?;
+    if read < ident.len() || ident[..4] != *b"\x7fELF" {
+        return Ok(None);
+    }
+    let machine = [ident[18], ident[19]];
+    let (machine, abi) = match (ident[4], ident[5]) {
+        (1, 1) => (u16::from_le_bytes(machine), Abi::Le32),
+        (1, 2) => (u16::from_be_bytes(machine), Abi::Be32),
+        (2, 1) => (u16::from_le_bytes(machine), Abi::Le64),
+        (2, 2) => (u16::from_be_bytes(machine), Abi::Be64),
+        _ => return Ok(None),
+    };
+    Ok(Some((machine, abi)))
+}
+
+/// Read until `buf` is full or the end of `reader` is reached.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..])? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    Ok(read)
+}
+
+/// A readable name of the architecture an ELF binary was built for.
+fn describe(machine: u16, abi: Abi) -> String {
+    match ARCHES
+        .iter()
+        .find(|(_, machines, arch_abi)| machines.contains(&machine) && *arch_abi == abi)
+    {
+        Some((name, _, _)) => name.to_string(),
+        None => format!("machine type {}", machine),
+    }
+}
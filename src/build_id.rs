@@ -0,0 +1,56 @@
+//! Links from the build-id of ELF files to the files, which crash tools use to find binaries.
+
+use crate::elf;
+use crate::{AppError, FileEntry, FileKind};
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+
+/// The build-id links of installed binaries are kept in `.build-id` below this directory.
+const LINK_DIR: &str = "/usr/lib";
+
+/// The path of the link for `build_id` below `dir`, e.g. `/usr/lib/.build-id/xx/yyyy`.
+pub fn link_path(dir: &str, build_id: &str) -> String {
+    format!("{}/.build-id/{}/{}", dir, &build_id[..2], &build_id[2..])
+}
+
+/// A symlink `/usr/lib/.build-id/xx/yyyy` for every ELF file with a build-id among `files`.
+///
+/// Two files with the same build-id would need the same link, which is an error. Links already
+/// in `linked`, the ones of the other packages built for the same architecture, are left out,
+/// so packages that ship the same binary can be installed together.
+pub fn links(
+    files: &[FileEntry],
+    linked: &mut BTreeSet<String>,
+) -> Result<Vec<FileEntry>, AppError> {
+    let mut by_id: BTreeMap<String, &FileEntry> = BTreeMap::new();
+    for file in files {
+        if matches!(file.kind, FileKind::Symlink) {
+            continue;
+        }
+        let build_id = match elf::build_id(&file.source)? {
+            Some(build_id) => build_id,
+            None => continue,
+        };
+        if let Some(other) = by_id.insert(build_id.clone(), file) {
+            return Err(app_err!(
+                "{} and {} have the same build-id {}",
+                other.dest,
+                file.dest,
+                build_id
+            ));
+        }
+    }
+    Ok(by_id
+        .into_iter()
+        .map(|(build_id, file)| {
+            FileEntry {
+                // relative to /usr/lib/.build-id/xx
+                source: PathBuf::from(format!("../../../..{}", file.dest)),
+                dest: link_path(LINK_DIR, &build_id),
+                kind: FileKind::Symlink,
+            }
+        })
+        .filter(|link| linked.insert(link.dest.clone()))
+        .collect())
+}
@@ -1,18 +1,36 @@
-use clap::{App, Arg};
+use clap::{App, AppSettings, Arg, SubCommand};
 pub const NAME_ARG: &str = "name";
+pub const SUBPACKAGE_ARG: &str = "subpackage";
 pub const OUT_ARG: &str = "out";
+pub const OUT_DIR_ARG: &str = "out-dir";
 pub const VERSION_ARG: &str = "version";
 pub const EPOCH_ARG: &str = "epoch";
 pub const LICENSE_ARG: &str = "license";
 pub const ARCH_ARG: &str = "arch";
 pub const RELEASE_ARG: &str = "release";
 pub const DESC_ARG: &str = "desc";
+pub const DESCRIPTION_FILE_ARG: &str = "description-file";
+pub const SUMMARY_ARG: &str = "summary";
+pub const URL_ARG: &str = "url";
+pub const VENDOR_ARG: &str = "vendor";
+pub const PACKAGER_ARG: &str = "packager";
+pub const GROUP_ARG: &str = "group";
+pub const DISTRIBUTION_ARG: &str = "distribution";
+pub const BUG_URL_ARG: &str = "bugurl";
 pub const FILE_ARG: &str = "file";
 pub const EXEC_FILE_ARG: &str = "exec-file";
+pub const EXEC_FILE_FOR_ARG: &str = "exec-file-for";
 pub const DOC_FILE_ARG: &str = "doc-file";
 pub const CONFIG_FILE_ARG: &str = "config-file";
 pub const DIR_ARG: &str = "dir";
 pub const COMPRESSION_ARG: &str = "compression";
+pub const THREADS_ARG: &str = "threads";
+pub const FILE_DIGEST_ARG: &str = "file-digest";
+pub const DEBUGINFO_ARG: &str = "debuginfo";
+pub const NO_BUILD_ID_LINKS_ARG: &str = "no-build-id-links";
+pub const SRPM_ARG: &str = "srpm";
+pub const SOURCE_ARG: &str = "source";
+pub const EMIT_SPEC_ARG: &str = "emit-spec";
 pub const CHANGELOG_ARG: &str = "changelog";
 pub const REQUIRES_ARG: &str = "requires";
 pub const OBSOLETES_ARG: &str = "obsoletes";
@@ -23,19 +41,42 @@ pub const POST_INSTALL_SCRIPTLET_ARG: &str = "post-install-script";
 pub const PRE_UNINSTALL_SCRIPTLET_ARG: &str = "pre-uninstall-script";
 pub const POST_UNINSTALL_SCRIPTLET_ARG: &str = "post-uninstall-script";
 pub const SIGN_WITH_PGP_ASC_ARG: &str = "sign-with-pgp-asc";
-const VERSION: &'static str = env!("CARGO_PKG_VERSION");
+pub const SOURCE_DATE_EPOCH_ARG: &str = "source-date-epoch";
+pub const PGP_PASSPHRASE_ENV_ARG: &str = "pgp-passphrase-env";
+pub const PGP_PASSPHRASE_FD_ARG: &str = "pgp-passphrase-fd";
+pub const PGP_PASSPHRASE_FILE_ARG: &str = "pgp-passphrase-file";
+pub const SIGN_SUBCOMMAND: &str = "sign";
+pub const VERIFY_SUBCOMMAND: &str = "verify";
+pub const QUERY_SUBCOMMAND: &str = "query";
+pub const JSON_ARG: &str = "json";
+pub const EXTRACT_SUBCOMMAND: &str = "extract";
+pub const DIRECTORY_ARG: &str = "directory";
+pub const DIFF_SUBCOMMAND: &str = "diff";
+pub const OLD_PACKAGE_ARG: &str = "old-package";
+pub const NEW_PACKAGE_ARG: &str = "new-package";
+pub const REPO_SUBCOMMAND: &str = "repo";
+pub const UPDATE_ARG: &str = "update";
+pub const KEY_ARG: &str = "key";
+pub const PACKAGE_ARG: &str = "package";
+const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 pub fn build_cli() -> App<'static, 'static> {
-    let supported_compression_options = ["gzip","zstd", "none"];
     App::new("rpm-builder")
           .version(VERSION)
           .author("René R. <richterrettich@gmail.com>")
           .about("Build rpms with ease")
+          .setting(AppSettings::SubcommandsNegateReqs)
+          .setting(AppSettings::VersionlessSubcommands)
           .arg(Arg::with_name(OUT_ARG)
                .long(OUT_ARG)
                .short("o")
                .value_name("OUT")
-               .help("Specify an out file")
+               .help("Specify an out file, {name}, {epoch}, {version}, {release} and {arch} are replaced. Defaults to {name}-{version}-{release}.{arch}.rpm")
+               .takes_value(true))
+          .arg(Arg::with_name(OUT_DIR_ARG)
+               .long(OUT_DIR_ARG)
+               .value_name("OUT_DIR")
+               .help("Write the rpm to this directory, which is created if missing")
                .takes_value(true))
           .arg(Arg::with_name(VERSION_ARG)
                .long(VERSION_ARG)
@@ -58,7 +99,7 @@ pub fn build_cli() -> App<'static, 'static> {
           .arg(Arg::with_name(ARCH_ARG)
                .long(ARCH_ARG)
                .value_name("ARCH")
-               .help("Specify the target architecture")
+               .help("Specify the target architecture, e.g. x86_64, aarch64 or noarch")
                .default_value("x86_64")
                .takes_value(true))
           .arg(Arg::with_name(RELEASE_ARG)
@@ -71,7 +112,52 @@ pub fn build_cli() -> App<'static, 'static> {
                .long(DESC_ARG)
                .value_name("DESC")
                .help("Give a description of the package")
-               .default_value("")
+               .takes_value(true)
+               .multiple(true)
+               .number_of_values(1))
+          .arg(Arg::with_name(DESCRIPTION_FILE_ARG)
+               .long(DESCRIPTION_FILE_ARG)
+               .value_name("PATH")
+               .help("Read the description of the package from a file. Markdown is reduced to plain text")
+               .takes_value(true)
+               .multiple(true)
+               .number_of_values(1))
+          .arg(Arg::with_name(SUMMARY_ARG)
+               .long(SUMMARY_ARG)
+               .value_name("SUMMARY")
+               .help("Give a one line summary of the package, without a trailing period. Defaults to the first line of the description")
+               .takes_value(true)
+               .multiple(true)
+               .number_of_values(1))
+          .arg(Arg::with_name(URL_ARG)
+               .long(URL_ARG)
+               .value_name("URL")
+               .help("Specify the url of the project")
+               .takes_value(true))
+          .arg(Arg::with_name(VENDOR_ARG)
+               .long(VENDOR_ARG)
+               .value_name("VENDOR")
+               .help("Specify the vendor of the package")
+               .takes_value(true))
+          .arg(Arg::with_name(PACKAGER_ARG)
+               .long(PACKAGER_ARG)
+               .value_name("PACKAGER")
+               .help("Specify who built the package, e.g. 'Jane Doe <jane@example.com>'")
+               .takes_value(true))
+          .arg(Arg::with_name(GROUP_ARG)
+               .long(GROUP_ARG)
+               .value_name("GROUP")
+               .help("Specify the group of the package")
+               .takes_value(true))
+          .arg(Arg::with_name(DISTRIBUTION_ARG)
+               .long(DISTRIBUTION_ARG)
+               .value_name("DISTRIBUTION")
+               .help("Specify the distribution the package belongs to")
+               .takes_value(true))
+          .arg(Arg::with_name(BUG_URL_ARG)
+               .long(BUG_URL_ARG)
+               .value_name("BUGURL")
+               .help("Specify the url bugs should be reported to")
                .takes_value(true))
           .arg(Arg::with_name(FILE_ARG)
                .long("file")
@@ -87,6 +173,13 @@ pub fn build_cli() -> App<'static, 'static> {
                .takes_value(true)
                .multiple(true)
                .number_of_values(1))
+          .arg(Arg::with_name(EXEC_FILE_FOR_ARG)
+               .long(EXEC_FILE_FOR_ARG)
+               .value_names(&["ARCH", "EXEC_FILE"])
+               .help("add a executable-file to the rpm of the given architecture, one rpm is built per architecture")
+               .takes_value(true)
+               .multiple(true)
+               .number_of_values(2))
           .arg(Arg::with_name(DOC_FILE_ARG)
                .long("doc-file")
                .value_name("DOC_FILE")
@@ -111,12 +204,42 @@ pub fn build_cli() -> App<'static, 'static> {
           .arg(Arg::with_name(COMPRESSION_ARG)
                .long("compression")
                .value_name("COMPRESSION")
-               .help("specify the compression algorithm. Currently only gzip and zstd are supported")
+               .help("specify the compression algorithm and optionally its level, e.g. zstd:19. Supported are gzip, zstd, xz, lzma, bzip2 and none")
                .takes_value(true)
                .default_value("none")
                .multiple(true)
-               .possible_values(&supported_compression_options)
                .number_of_values(1))
+          .arg(Arg::with_name(FILE_DIGEST_ARG)
+               .long(FILE_DIGEST_ARG)
+               .value_name("ALGORITHM")
+               .help("the digest algorithm recorded for every file")
+               .takes_value(true)
+               .default_value("sha256")
+               .possible_values(&["sha256", "sha512"]))
+          .arg(Arg::with_name(THREADS_ARG)
+               .long(THREADS_ARG)
+               .value_name("N")
+               .help("compress the payload with N threads, only supported for zstd and xz. The output is reproducible for a given N")
+               .takes_value(true))
+          .arg(Arg::with_name(DEBUGINFO_ARG)
+               .long(DEBUGINFO_ARG)
+               .help("strip ELF files and put their debug information into a <name>-debuginfo rpm, needs objcopy"))
+          .arg(Arg::with_name(NO_BUILD_ID_LINKS_ARG)
+               .long(NO_BUILD_ID_LINKS_ARG)
+               .help("do not add /usr/lib/.build-id links to the ELF files of the rpm"))
+          .arg(Arg::with_name(SRPM_ARG)
+               .long(SRPM_ARG)
+               .help("also build a <name>-<version>-<release>.src.rpm with a generated spec file and the --source archives"))
+          .arg(Arg::with_name(SOURCE_ARG)
+               .long(SOURCE_ARG)
+               .value_name("SOURCE")
+               .help("add a source archive to the spec file and the source rpm")
+               .takes_value(true)
+               .multiple(true)
+               .number_of_values(1))
+          .arg(Arg::with_name(EMIT_SPEC_ARG)
+               .long(EMIT_SPEC_ARG)
+               .help("print a spec file building the same packages with rpmbuild instead of building them"))
           .arg(Arg::with_name(CHANGELOG_ARG)
                .long("changelog")
                .value_name("CHANGELOG_ENTRY")
@@ -157,36 +280,146 @@ pub fn build_cli() -> App<'static, 'static> {
                .value_name("PREINSTALLSCRIPT")
                .help("path to a file that contains the pre installation script")
                .takes_value(true)
-               .multiple(false)
+               .multiple(true)
                .number_of_values(1))
           .arg(Arg::with_name(POST_INSTALL_SCRIPTLET_ARG)
                .long("post-install-script")
                .value_name("POSTINSTALLSCRIPT")
                .help("path to a file that contains the post installation script")
                .takes_value(true)
-               .multiple(false)
+               .multiple(true)
                .number_of_values(1))
           .arg(Arg::with_name(PRE_UNINSTALL_SCRIPTLET_ARG)
                .long("pre-uninstall-script")
                .value_name("PRE_UNINSTALL_SCRIPT")
                .help("path to a file that contains a pre uninstall script")
                .takes_value(true)
-               .multiple(false)
+               .multiple(true)
                .number_of_values(1))
           .arg(Arg::with_name(POST_UNINSTALL_SCRIPTLET_ARG)
                .long("post-uninstall-script")
                .value_name("POST_UNINSTALL_SCRIPT")
                .help("path to a file that contains a post uninstall script")
                .takes_value(true)
-               .multiple(false)
+               .multiple(true)
                .number_of_values(1))
           .arg(Arg::with_name(NAME_ARG)
                .help("Specify the name of your package")
                .required(true))
+          .arg(Arg::with_name(SUBPACKAGE_ARG)
+               .long(SUBPACKAGE_ARG)
+               .value_name("SUBPACKAGE")
+               .help("Build the subpackage <name>-<SUBPACKAGE>. Files, dependencies, scriptlets, summary and description given after it belong to the subpackage")
+               .takes_value(true)
+               .multiple(true)
+               .number_of_values(1))
           .arg(Arg::with_name(SIGN_WITH_PGP_ASC_ARG)
                .long("sign-with-pgp-asc")
                .value_name("SIGN_WITH_PGP_ASC")
                .takes_value(true)
                .number_of_values(1)
                .help("sign this package with the specified pgp secret key"))
+          .args(&passphrase_args())
+          .arg(Arg::with_name(SOURCE_DATE_EPOCH_ARG)
+               .long(SOURCE_DATE_EPOCH_ARG)
+               .value_name("SECONDS")
+               .env("SOURCE_DATE_EPOCH")
+               .takes_value(true)
+               .help("clamp the build time, file modification times and signature times to this unix timestamp, making builds reproducible"))
+          .subcommand(SubCommand::with_name(SIGN_SUBCOMMAND)
+               .about("Sign existing rpm packages, replacing any present signature")
+               .arg(Arg::with_name(KEY_ARG)
+                    .long(KEY_ARG)
+                    .value_name("KEY")
+                    .help("the pgp secret key to sign the packages with")
+                    .takes_value(true)
+                    .required(true))
+               .args(&passphrase_args())
+               .arg(Arg::with_name(PACKAGE_ARG)
+                    .value_name("PACKAGE")
+                    .help("the rpm packages to sign")
+                    .required(true)
+                    .multiple(true)))
+          .subcommand(SubCommand::with_name(VERIFY_SUBCOMMAND)
+               .about("Verify digests and signatures of rpm packages")
+               .arg(Arg::with_name(KEY_ARG)
+                    .long(KEY_ARG)
+                    .value_name("KEY")
+                    .help("a pgp public key the signatures are checked against. Can be given multiple times")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1))
+               .arg(Arg::with_name(PACKAGE_ARG)
+                    .value_name("PACKAGE")
+                    .help("the rpm packages to verify")
+                    .required(true)
+                    .multiple(true)))
+          .subcommand(SubCommand::with_name(QUERY_SUBCOMMAND)
+               .about("Show the metadata and file list of rpm packages")
+               .arg(Arg::with_name(JSON_ARG)
+                    .long(JSON_ARG)
+                    .help("print the metadata as a json array with one object per package"))
+               .arg(Arg::with_name(PACKAGE_ARG)
+                    .value_name("PACKAGE")
+                    .help("the rpm packages to query")
+                    .required(true)
+                    .multiple(true)))
+          .subcommand(SubCommand::with_name(EXTRACT_SUBCOMMAND)
+               .about("Unpack the payload of an rpm package")
+               .arg(Arg::with_name(DIRECTORY_ARG)
+                    .short("C")
+                    .long(DIRECTORY_ARG)
+                    .value_name("DIR")
+                    .help("the directory the files are written to")
+                    .takes_value(true)
+                    .default_value("."))
+               .arg(Arg::with_name(PACKAGE_ARG)
+                    .value_name("PACKAGE")
+                    .help("the rpm package to extract")
+                    .required(true)))
+          .subcommand(SubCommand::with_name(DIFF_SUBCOMMAND)
+               .about("Compare the metadata and file lists of two rpm packages. Exits with 1 if they differ")
+               .arg(Arg::with_name(JSON_ARG)
+                    .long(JSON_ARG)
+                    .help("print the differences as json"))
+               .arg(Arg::with_name(OLD_PACKAGE_ARG)
+                    .value_name("OLD")
+                    .help("the package to compare against")
+                    .required(true))
+               .arg(Arg::with_name(NEW_PACKAGE_ARG)
+                    .value_name("NEW")
+                    .help("the package to compare")
+                    .required(true)))
+          .subcommand(SubCommand::with_name(REPO_SUBCOMMAND)
+               .about("Generate yum/dnf repository metadata for all rpm packages in a directory")
+               .arg(Arg::with_name(UPDATE_ARG)
+                    .long(UPDATE_ARG)
+                    .help("update existing metadata in place. Only packages whose size or modification time changed are parsed again"))
+               .arg(Arg::with_name(DIRECTORY_ARG)
+                    .value_name("DIR")
+                    .help("the directory containing the packages. The metadata is written to DIR/repodata")
+                    .required(true)))
+}
+
+fn passphrase_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name(PGP_PASSPHRASE_ENV_ARG)
+            .long(PGP_PASSPHRASE_ENV_ARG)
+            .value_name("VARIABLE")
+            .takes_value(true)
+            .conflicts_with_all(&[PGP_PASSPHRASE_FD_ARG, PGP_PASSPHRASE_FILE_ARG])
+            .help("read the passphrase of the pgp secret key from the given environment variable"),
+        Arg::with_name(PGP_PASSPHRASE_FD_ARG)
+            .long(PGP_PASSPHRASE_FD_ARG)
+            .value_name("FD")
+            .takes_value(true)
+            .conflicts_with_all(&[PGP_PASSPHRASE_ENV_ARG, PGP_PASSPHRASE_FILE_ARG])
+            .help("read the passphrase of the pgp secret key from the given file descriptor"),
+        Arg::with_name(PGP_PASSPHRASE_FILE_ARG)
+            .long(PGP_PASSPHRASE_FILE_ARG)
+            .value_name("PATH")
+            .takes_value(true)
+            .conflicts_with_all(&[PGP_PASSPHRASE_ENV_ARG, PGP_PASSPHRASE_FD_ARG])
+            .help("read the passphrase of the pgp secret key from the given file"),
+    ]
 }
@@ -0,0 +1,248 @@
+//! Compression of the payload, with a selectable algorithm and level.
+
+use crate::cli;
+use crate::header::{Header, Value};
+use crate::AppError;
+
+use rpm::IndexTag;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+/// A compression algorithm supported by rpm for the payload.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Algorithm {
+    None,
+    Gzip,
+    Zstd,
+    Xz,
+    Lzma,
+    Bzip2,
+}
+
+impl Algorithm {
+    /// The name used on the command line and in the payload compressor tag.
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::None => "none",
+            Algorithm::Gzip => "gzip",
+            Algorithm::Zstd => "zstd",
+            Algorithm::Xz => "xz",
+            Algorithm::Lzma => "lzma",
+            Algorithm::Bzip2 => "bzip2",
+        }
+    }
+
+    /// The range of valid levels and the level used if none is given.
+    fn levels(self) -> (u32, u32, u32) {
+        match self {
+            Algorithm::None => (0, 0, 0),
+            Algorithm::Gzip => (1, 9, 9),
+            // 19 is what fedora uses, 20 and above need a lot of memory to unpack
+            Algorithm::Zstd => (1, 22, 19),
+            Algorithm::Xz | Algorithm::Lzma => (0, 9, 6),
+            Algorithm::Bzip2 => (1, 9, 9),
+        }
+    }
+}
+
+impl FromStr for Algorithm {
+    type Err = AppError;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        match raw {
+            "none" => Ok(Algorithm::None),
+            "gzip" => Ok(Algorithm::Gzip),
+            "zstd" => Ok(Algorithm::Zstd),
+            "xz" => Ok(Algorithm::Xz),
+            "lzma" => Ok(Algorithm::Lzma),
+            "bzip2" => Ok(Algorithm::Bzip2),
+            _ => Err(app_err!(
+                "unknown compression {}, expected one of none, gzip, zstd, xz, lzma or bzip2",
+                raw
+            )),
+        }
+    }
+}
+
+/// The payload compression given with `--compression <algorithm>[:<level>]` and `--threads`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Compression {
+    pub algorithm: Algorithm,
+    pub level: u32,
+    /// Compress with this many worker threads, single threaded if not set.
+    pub threads: Option<u32>,
+}
+
+/// Read the payload compression from the command line.
+pub(crate) fn compression(matches: &clap::ArgMatches) -> Result<Compression, AppError> {
+    let mut compression: Compression = matches.value_of(cli::COMPRESSION_ARG).unwrap().parse()?;
+    if let Some(raw) = matches.value_of(cli::THREADS_ARG) {
+        let threads = match raw.parse() {
+            Ok(threads) if threads > 0 => threads,
+            _ => return Err(app_err!("invalid number of threads {}", raw)),
+        };
+        if !matches!(compression.algorithm, Algorithm::Zstd | Algorithm::Xz) {
+            return Err(app_err!(
+                "--{} is only supported for zstd and xz compression",
+                cli::THREADS_ARG
+            ));
+        }
+        compression.threads = Some(threads);
+    }
+    Ok(compression)
+}
+
+impl FromStr for Compression {
+    type Err = AppError;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        let (name, level) = match raw.find(':') {
+            Some(index) => (&raw[..index], Some(&raw[index + 1..])),
+            None => (raw, None),
+        };
+        let algorithm: Algorithm = name.parse()?;
+        let (min, max, default) = algorithm.levels();
+        let level = match level {
+            None => default,
+            Some(_) if algorithm == Algorithm::None => {
+                return Err(app_err!("no compression level can be given for none"))
+            }
+            Some(level) => match level.parse() {
+                Ok(level) if level >= min && level <= max => level,
+                _ => {
+                    return Err(app_err!(
+                        "invalid {} compression level {}, expected {} to {}",
+                        name,
+                        level,
+                        min,
+                        max
+                    ))
+                }
+            },
+        };
+        Ok(Compression {
+            algorithm,
+            level,
+            threads: None,
+        })
+    }
+}
+
+impl Compression {
+    /// The payload flags as rpm records them, only the level even with several threads.
+    pub fn flags(&self) -> String {
+        self.level.to_string()
+    }
+
+    /// The `_binary_payload` macro rpmbuild uses for this compression, e.g. `w19.zstdio` or
+    /// `w19T8.zstdio` with eight threads.
+    pub fn payload_macro(&self) -> String {
+        let io = match self.algorithm {
+            Algorithm::None => return "w.ufdio".to_string(),
+            Algorithm::Gzip => "gzdio",
+            Algorithm::Zstd => "zstdio",
+            Algorithm::Xz => "xzdio",
+            Algorithm::Lzma => "lzdio",
+            Algorithm::Bzip2 => "bzdio",
+        };
+        match self.threads {
+            Some(threads) => format!("w{}T{}.{}", self.level, threads, io),
+            None => format!("w{}.{}", self.level, io),
+        }
+    }
+
+    /// Wrap `writer` in an encoder for this compression.
+    pub fn encoder<W: Write>(&self, writer: W) -> Result<Encoder<W>, AppError> {
+        Ok(match self.algorithm {
+            Algorithm::None => Encoder::None(writer),
+            Algorithm::Gzip => Encoder::Gzip(flate2::write::GzEncoder::new(
+                writer,
+                flate2::Compression::new(self.level),
+            )),
+            Algorithm::Zstd => {
+                let mut encoder = zstd::stream::write::Encoder::new(writer, self.level as i32)?;
+                if let Some(threads) = self.threads {
+                    // the output of zstd does not depend on the number of workers
+                    encoder.multithread(threads)?;
+                }
+                Encoder::Zstd(encoder)
+            }
+            Algorithm::Xz => match self.threads {
+                Some(threads) => {
+                    // the input is split into blocks sized by the preset, not by the
+                    // number of threads, which keeps the output independent of it
+                    let stream = xz2::stream::MtStreamBuilder::new()
+                        .preset(self.level)
+                        .threads(threads)
+                        .encoder()
+                        .map_err(|e| app_err!("unable to set up xz compression: {}", e))?;
+                    Encoder::Xz(xz2::write::XzEncoder::new_stream(writer, stream))
+                }
+                None => Encoder::Xz(xz2::write::XzEncoder::new(writer, self.level)),
+            },
+            Algorithm::Lzma => {
+                let options = xz2::stream::LzmaOptions::new_preset(self.level)
+                    .map_err(|e| app_err!("unable to set up lzma compression: {}", e))?;
+                let stream = xz2::stream::Stream::new_lzma_encoder(&options)
+                    .map_err(|e| app_err!("unable to set up lzma compression: {}", e))?;
+                Encoder::Xz(xz2::write::XzEncoder::new_stream(writer, stream))
+            }
+            Algorithm::Bzip2 => Encoder::Bzip2(bzip2::write::BzEncoder::new(
+                writer,
+                bzip2::Compression::new(self.level),
+            )),
+        })
+    }
+
+    /// Record the compression in the payload compressor and flags tags of `header`.
+    pub fn record(&self, header: &mut Header) {
+        if self.algorithm == Algorithm::None {
+            return;
+        }
+        header.set(
+            IndexTag::RPMTAG_PAYLOADCOMPRESSOR,
+            Value::String(self.algorithm.name().to_string()),
+        );
+        header.set(IndexTag::RPMTAG_PAYLOADFLAGS, Value::String(self.flags()));
+    }
+}
+
+/// A writer compressing everything written to it.
+pub enum Encoder<W: Write> {
+    None(W),
+    Gzip(flate2::write::GzEncoder<W>),
+    Zstd(zstd::stream::write::Encoder<'static, W>),
+    Xz(xz2::write::XzEncoder<W>),
+    Bzip2(bzip2::write::BzEncoder<W>),
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Encoder::None(writer) => writer.write(buf),
+            Encoder::Gzip(encoder) => encoder.write(buf),
+            Encoder::Zstd(encoder) => encoder.write(buf),
+            Encoder::Xz(encoder) => encoder.write(buf),
+            Encoder::Bzip2(encoder) => encoder.write(buf),
+        }
+    }
+
+    /// Flushing a compressor ends the current block, which costs compression ratio and is not
+    /// supported for lzma at all. Everything is written out by [`finish`](Encoder::finish).
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<W: Write> Encoder<W> {
+    /// Write the end of the compressed stream and return the inner writer.
+    pub fn finish(self) -> io::Result<W> {
+        match self {
+            Encoder::None(writer) => Ok(writer),
+            Encoder::Gzip(encoder) => encoder.finish(),
+            Encoder::Zstd(encoder) => encoder.finish(),
+            Encoder::Xz(encoder) => encoder.finish(),
+            Encoder::Bzip2(encoder) => encoder.finish(),
+        }
+    }
+}
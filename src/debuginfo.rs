@@ -0,0 +1,96 @@
+//! Splitting the debug information of ELF files into a separate debuginfo package.
+
+use crate::build_id;
+use crate::elf;
+use crate::{AppError, FileEntry, FileKind};
+
+use std::collections::BTreeSet;
+use std::ffi::{OsStr, OsString};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The directory debuggers look up separate debug information in.
+const DEBUG_DIR: &str = "/usr/lib/debug";
+
+/// Replace every ELF file among `files` with a stripped copy in `tmp_dir` and return the files
+/// of the debuginfo package, sorted by their destination.
+///
+/// The debug information of `/usr/bin/app` is installed as `/usr/lib/debug/usr/bin/app.debug`,
+/// which the stripped file references by its `.gnu_debuglink` section. Files with a build-id
+/// are also linked from `/usr/lib/debug/.build-id/xx/yyyy.debug`, unless `linked` already holds
+/// the link.
+pub fn split(
+    files: &mut [FileEntry],
+    tmp_dir: &Path,
+    linked: &mut BTreeSet<String>,
+) -> Result<Vec<FileEntry>, AppError> {
+    let mut debug_files = Vec::new();
+    for (index, file) in files.iter_mut().enumerate() {
+        if matches!(file.kind, FileKind::Symlink) || !elf::is_elf(&file.source)? {
+            continue;
+        }
+        let dest = &file.dest;
+        let file_name = Path::new(&dest)
+            .file_name()
+            .ok_or_else(|| app_err!("path does not have filename"))?;
+        // the debuglink only holds the file name, so every file gets a directory of its own
+        let dir = tmp_dir.join(index.to_string());
+        std::fs::create_dir(&dir)?;
+        let stripped = dir.join(file_name);
+        let mut debug = stripped.clone().into_os_string();
+        debug.push(".debug");
+        let debug = PathBuf::from(debug);
+
+        objcopy(&[
+            OsStr::new("--only-keep-debug"),
+            file.source.as_os_str(),
+            debug.as_os_str(),
+        ])?;
+        let mut debuglink = OsString::from("--add-gnu-debuglink=");
+        debuglink.push(&debug);
+        objcopy(&[
+            OsStr::new("--strip-unneeded"),
+            &debuglink,
+            file.source.as_os_str(),
+            stripped.as_os_str(),
+        ])?;
+        // debug information is only read, never executed
+        std::fs::set_permissions(&debug, std::fs::Permissions::from_mode(0o644))?;
+
+        if let Some(build_id) = elf::build_id(&file.source)? {
+            let link = format!("{}.debug", build_id::link_path(DEBUG_DIR, &build_id));
+            if linked.insert(link.clone()) {
+                debug_files.push(FileEntry {
+                    source: PathBuf::from(format!("../..{}.debug", dest)),
+                    dest: link,
+                    kind: FileKind::Symlink,
+                });
+            }
+        }
+        debug_files.push(FileEntry {
+            source: debug,
+            dest: format!("{}{}.debug", DEBUG_DIR, dest),
+            kind: FileKind::Regular,
+        });
+        file.source = stripped;
+    }
+    debug_files.sort_by(|a, b| a.dest.cmp(&b.dest));
+    Ok(debug_files)
+}
+
+/// Run objcopy, keeping permissions and modification times.
+fn objcopy(args: &[&OsStr]) -> Result<(), AppError> {
+    let output = Command::new("objcopy")
+        .arg("--preserve-dates")
+        .args(args)
+        .output()
+        .map_err(|e| app_err!("unable to run objcopy: {}", e))?;
+    if !output.status.success() {
+        return Err(app_err!(
+            "objcopy failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,113 @@
+//! Description and summary of a package.
+
+use crate::cli;
+use crate::subpackage::PackageArgs;
+use crate::AppError;
+
+use regex::Regex;
+
+/// Read the description given for `package`, either directly or from a Markdown file.
+pub fn description(package: &PackageArgs) -> Result<Option<String>, AppError> {
+    match (
+        package.value_of(cli::DESC_ARG)?,
+        package.value_of(cli::DESCRIPTION_FILE_ARG)?,
+    ) {
+        (Some(_), Some(_)) => Err(app_err!(
+            "--{} and --{} can not be used together",
+            cli::DESC_ARG,
+            cli::DESCRIPTION_FILE_ARG
+        )),
+        (Some(description), None) => Ok(Some(description.to_string())),
+        (None, Some(path)) => {
+            let markdown = std::fs::read_to_string(path)
+                .map_err(|e| app_err!("unable to read description file {}: {}", path, e))?;
+            Ok(Some(strip_markdown(&markdown)))
+        }
+        (None, None) => Ok(None),
+    }
+}
+
+/// The summary given on the command line, or the first line of the description.
+///
+/// An explicit summary has to follow the distribution guidelines, one derived from
+/// the description is adjusted to them.
+pub fn summary(summary: Option<&str>, description: &str) -> Result<String, AppError> {
+    match summary {
+        Some(summary) => {
+            if summary.contains('\n') {
+                return Err(app_err!("the summary has to be a single line"));
+            }
+            if summary.trim_end().ends_with('.') {
+                return Err(app_err!(
+                    "the summary must not end with a period: {}",
+                    summary
+                ));
+            }
+            Ok(summary.trim().to_string())
+        }
+        None => Ok(description
+            .lines()
+            .map(|line| line.trim())
+            .find(|line| !line.is_empty())
+            .unwrap_or_default()
+            .trim_end_matches('.')
+            .to_string()),
+    }
+}
+
+/// Reduce Markdown to plain text, keeping paragraphs, lists and code blocks readable.
+pub fn strip_markdown(markdown: &str) -> String {
+    let heading = Regex::new(r"^#{1,6}\s+(.*?)(\s+#+)?\s*$").unwrap();
+    let underline = Regex::new(r"^(=+|-+|\*{3,}|_{3,})\s*$").unwrap();
+    let quote = Regex::new(r"^\s*>\s?").unwrap();
+    let list = Regex::new(r"^(\s*)[*+-]\s+").unwrap();
+    let image = Regex::new(r"!\[([^\]]*)\]\([^)]*\)").unwrap();
+    let link = Regex::new(r"\[([^\]]+)\]\(([^)\s]+)[^)]*\)").unwrap();
+    let code = Regex::new(r"`([^`]+)`").unwrap();
+    let strong = Regex::new(r"(\*\*|__)([^*_]+)(\*\*|__)").unwrap();
+    let emphasis = Regex::new(r"\*([^*\s][^*]*)\*|\b_([^_]+)_\b").unwrap();
+
+    let mut lines: Vec<String> = Vec::new();
+    let mut in_code_block = false;
+    for line in markdown.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_code_block = !in_code_block;
+            continue;
+        }
+        if in_code_block {
+            lines.push(line.to_string());
+            continue;
+        }
+        if underline.is_match(line) {
+            // setext heading underlines and horizontal rules carry no text
+            continue;
+        }
+        let line = heading.replace(line, "$1");
+        let line = quote.replace(&line, "");
+        let line = list.replace(&line, "$1- ");
+        let line = image.replace_all(&line, "$1");
+        let line = link.replace_all(&line, "$1 ($2)");
+        let line = code.replace_all(&line, "$1");
+        let line = strong.replace_all(&line, "$2");
+        let line = emphasis.replace_all(&line, "$1$2");
+        lines.push(line.trim_end().to_string());
+    }
+
+    // collapse runs of blank lines, they are left behind by removed markup
+    let mut out = String::new();
+    let mut blank = true;
+    for line in lines {
+        if line.is_empty() {
+            if !blank {
+                out.push('\n');
+            }
+            blank = true;
+        } else {
+            out.push_str(&line);
+            out.push('\n');
+            blank = false;
+        }
+    }
+    out.trim_end().to_string()
+}
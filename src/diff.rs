@@ -0,0 +1,278 @@
+use crate::cli;
+use crate::metadata::{format_mode, FileInfo, PackageInfo};
+use crate::package::Package;
+use crate::AppError;
+
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Debug, Serialize)]
+struct PackageDiff {
+    tags: Vec<TagChange>,
+    dependencies: Vec<DependencyChange>,
+    scriptlets: Vec<ScriptletChange>,
+    files: Vec<FileChange>,
+}
+
+#[derive(Debug, Serialize)]
+struct TagChange {
+    tag: &'static str,
+    old: String,
+    new: String,
+}
+
+#[derive(Debug, Serialize)]
+struct DependencyChange {
+    kind: &'static str,
+    added: Vec<String>,
+    removed: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ScriptletChange {
+    name: &'static str,
+    old: Option<String>,
+    new: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum FileStatus {
+    Added,
+    Removed,
+    Changed,
+}
+
+#[derive(Debug, Serialize)]
+struct FileChange {
+    path: String,
+    status: FileStatus,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    changes: Vec<String>,
+}
+
+impl PackageDiff {
+    fn is_empty(&self) -> bool {
+        self.tags.is_empty()
+            && self.dependencies.is_empty()
+            && self.scriptlets.is_empty()
+            && self.files.is_empty()
+    }
+}
+
+/// Compare two packages. Like `diff` it exits with 1 if they differ and with 2 on errors.
+pub fn diff(matches: &clap::ArgMatches) -> Result<(), AppError> {
+    match compare(matches) {
+        Ok(true) => Ok(()),
+        Ok(false) => std::process::exit(1),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(2)
+        }
+    }
+}
+
+/// Print the differences of the two packages, `true` if there are none.
+fn compare(matches: &clap::ArgMatches) -> Result<bool, AppError> {
+    let old_path = matches.value_of(cli::OLD_PACKAGE_ARG).unwrap();
+    let new_path = matches.value_of(cli::NEW_PACKAGE_ARG).unwrap();
+    let old = PackageInfo::from_header(&Package::open(old_path)?.header);
+    let new = PackageInfo::from_header(&Package::open(new_path)?.header);
+
+    let diff = PackageDiff {
+        tags: diff_tags(&old, &new),
+        dependencies: diff_dependencies(&old, &new),
+        scriptlets: diff_scriptlets(&old, &new),
+        files: diff_files(&old, &new),
+    };
+
+    if matches.is_present(cli::JSON_ARG) {
+        let json = serde_json::to_string_pretty(&diff)
+            .map_err(|e| app_err!("unable to serialize diff: {}", e))?;
+        println!("{}", json);
+    } else {
+        print_diff(&diff);
+    }
+
+    Ok(diff.is_empty())
+}
+
+fn diff_tags(old: &PackageInfo, new: &PackageInfo) -> Vec<TagChange> {
+    let epoch = |info: &PackageInfo| info.epoch.map(|e| e.to_string()).unwrap_or_default();
+    let tags = vec![
+        ("name", old.name.clone(), new.name.clone()),
+        ("epoch", epoch(old), epoch(new)),
+        ("version", old.version.clone(), new.version.clone()),
+        ("release", old.release.clone(), new.release.clone()),
+        ("arch", old.arch.clone(), new.arch.clone()),
+        ("license", old.license.clone(), new.license.clone()),
+        ("summary", old.summary.clone(), new.summary.clone()),
+        (
+            "description",
+            old.description.clone(),
+            new.description.clone(),
+        ),
+        ("url", old.url.clone(), new.url.clone()),
+        ("vendor", old.vendor.clone(), new.vendor.clone()),
+        ("packager", old.packager.clone(), new.packager.clone()),
+        ("group", old.group.clone(), new.group.clone()),
+        (
+            "distribution",
+            old.distribution.clone(),
+            new.distribution.clone(),
+        ),
+        ("bugurl", old.bug_url.clone(), new.bug_url.clone()),
+        ("buildhost", old.build_host.clone(), new.build_host.clone()),
+        ("sourcerpm", old.source_rpm.clone(), new.source_rpm.clone()),
+        ("size", old.size.to_string(), new.size.to_string()),
+    ];
+    // the build time is left out, it differs between any two builds
+    tags.into_iter()
+        .filter(|(_, old, new)| old != new)
+        .map(|(tag, old, new)| TagChange { tag, old, new })
+        .collect()
+}
+
+fn diff_dependencies(old: &PackageInfo, new: &PackageInfo) -> Vec<DependencyChange> {
+    let by_kind = |info: &PackageInfo| -> BTreeMap<&'static str, BTreeSet<String>> {
+        info.dependencies
+            .iter()
+            .map(|d| (d.kind, d.entries.iter().map(|e| e.to_string()).collect()))
+            .collect()
+    };
+    let old = by_kind(old);
+    let new = by_kind(new);
+    let empty = BTreeSet::new();
+    let kinds: BTreeSet<&'static str> = old.keys().chain(new.keys()).copied().collect();
+    kinds
+        .into_iter()
+        .map(|kind| {
+            let old = old.get(kind).unwrap_or(&empty);
+            let new = new.get(kind).unwrap_or(&empty);
+            DependencyChange {
+                kind,
+                added: new.difference(old).cloned().collect(),
+                removed: old.difference(new).cloned().collect(),
+            }
+        })
+        .filter(|c| !c.added.is_empty() || !c.removed.is_empty())
+        .collect()
+}
+
+fn diff_scriptlets(old: &PackageInfo, new: &PackageInfo) -> Vec<ScriptletChange> {
+    let by_name = |info: &PackageInfo| -> BTreeMap<&'static str, String> {
+        info.scriptlets
+            .iter()
+            .map(|s| {
+                let interpreter = s.interpreter.as_deref().unwrap_or_default();
+                (s.name, format!("#{}\n{}", interpreter, s.content))
+            })
+            .collect()
+    };
+    let old = by_name(old);
+    let new = by_name(new);
+    let names: BTreeSet<&'static str> = old.keys().chain(new.keys()).copied().collect();
+    names
+        .into_iter()
+        .filter(|name| old.get(name) != new.get(name))
+        .map(|name| ScriptletChange {
+            name,
+            old: old.get(name).cloned(),
+            new: new.get(name).cloned(),
+        })
+        .collect()
+}
+
+fn diff_files(old: &PackageInfo, new: &PackageInfo) -> Vec<FileChange> {
+    let by_path = |info: &PackageInfo| -> BTreeMap<String, FileInfo> {
+        info.files
+            .iter()
+            .map(|f| (f.path.clone(), f.clone()))
+            .collect()
+    };
+    let old = by_path(old);
+    let new = by_path(new);
+    let paths: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    paths
+        .into_iter()
+        .filter_map(|path| {
+            let (status, changes) = match (old.get(path), new.get(path)) {
+                (None, Some(_)) => (FileStatus::Added, Vec::new()),
+                (Some(_), None) => (FileStatus::Removed, Vec::new()),
+                (Some(old), Some(new)) => {
+                    let changes = file_changes(old, new);
+                    if changes.is_empty() {
+                        return None;
+                    }
+                    (FileStatus::Changed, changes)
+                }
+                (None, None) => return None,
+            };
+            Some(FileChange {
+                path: path.clone(),
+                status,
+                changes,
+            })
+        })
+        .collect()
+}
+
+fn file_changes(old: &FileInfo, new: &FileInfo) -> Vec<String> {
+    let mut changes = Vec::new();
+    if old.digest != new.digest || old.size != new.size {
+        changes.push("content".to_string());
+    }
+    if old.mode != new.mode {
+        changes.push(format!(
+            "mode {} -> {}",
+            format_mode(old.mode),
+            format_mode(new.mode)
+        ));
+    }
+    if old.user != new.user || old.group != new.group {
+        changes.push(format!(
+            "owner {}:{} -> {}:{}",
+            old.user, old.group, new.user, new.group
+        ));
+    }
+    if old.link_to != new.link_to {
+        changes.push(format!("link {} -> {}", old.link_to, new.link_to));
+    }
+    if old.flags != new.flags {
+        changes.push(format!(
+            "flags {} -> {}",
+            old.flags.join(","),
+            new.flags.join(",")
+        ));
+    }
+    changes
+}
+
+fn print_diff(diff: &PackageDiff) {
+    for change in diff.tags.iter() {
+        println!("{}: {:?} -> {:?}", change.tag, change.old, change.new);
+    }
+    for change in diff.dependencies.iter() {
+        for dependency in change.removed.iter() {
+            println!("- {}: {}", change.kind, dependency);
+        }
+        for dependency in change.added.iter() {
+            println!("+ {}: {}", change.kind, dependency);
+        }
+    }
+    for change in diff.scriptlets.iter() {
+        let status = match (&change.old, &change.new) {
+            (None, _) => "added",
+            (_, None) => "removed",
+            _ => "changed",
+        };
+        println!("{} scriptlet {}", change.name, status);
+    }
+    for change in diff.files.iter() {
+        match change.status {
+            FileStatus::Added => println!("+ {}", change.path),
+            FileStatus::Removed => println!("- {}", change.path),
+            FileStatus::Changed => println!("~ {} ({})", change.path, change.changes.join(", ")),
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use sha2::digest::DynDigest;
+use std::io::{self, Read, Write};
+
+/// A hasher for one of the `PGPHASHALGO_*` algorithms rpm records.
+pub fn hasher(algorithm: u64) -> Option<Box<dyn DynDigest>> {
+    use sha2::Digest;
+    Some(match algorithm as i32 {
+        rpm::PGPHASHALGO_MD5 => Box::new(md5::Md5::new()),
+        rpm::PGPHASHALGO_SHA1 => Box::new(sha1::Sha1::new()),
+        rpm::PGPHASHALGO_SHA256 => Box::new(sha2::Sha256::new()),
+        rpm::PGPHASHALGO_SHA384 => Box::new(sha2::Sha384::new()),
+        rpm::PGPHASHALGO_SHA512 => Box::new(sha2::Sha512::new()),
+        _ => return None,
+    })
+}
+
+/// The `PGPHASHALGO_*` value of a digest algorithm given by name, e.g. `sha256`.
+pub fn algorithm(name: &str) -> Option<u64> {
+    let algorithm = match name {
+        "md5" => rpm::PGPHASHALGO_MD5,
+        "sha1" => rpm::PGPHASHALGO_SHA1,
+        "sha256" => rpm::PGPHASHALGO_SHA256,
+        "sha384" => rpm::PGPHASHALGO_SHA384,
+        "sha512" => rpm::PGPHASHALGO_SHA512,
+        _ => return None,
+    };
+    Some(algorithm as u64)
+}
+
+/// Hex encoded digest of everything `reader` yields, using one of the `PGPHASHALGO_*` algorithms
+/// rpm records.
+pub fn hex_digest<R: Read>(algorithm: u64, mut reader: R) -> io::Result<Option<String>> {
+    let hasher = match hasher(algorithm) {
+        Some(hasher) => hasher,
+        None => return Ok(None),
+    };
+    let mut writer = DigestWriter::new(io::sink(), hasher);
+    io::copy(&mut reader, &mut writer)?;
+    let (_, digest) = writer.finish();
+    Ok(Some(hex::encode(digest)))
+}
+
+/// A writer passing everything on to `inner` while computing its digest.
+pub struct DigestWriter<W: Write> {
+    inner: W,
+    hasher: Box<dyn DynDigest>,
+}
+
+impl<W: Write> DigestWriter<W> {
+    pub fn new(inner: W, hasher: Box<dyn DynDigest>) -> Self {
+        DigestWriter { inner, hasher }
+    }
+
+    /// Return the inner writer and the digest of everything written.
+    pub fn finish(self) -> (W, Vec<u8>) {
+        (self.inner, self.hasher.finalize().to_vec())
+    }
+}
+
+impl<W: Write> Write for DigestWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,139 @@
+//! Reading the GNU build-id of ELF files.
+
+use crate::AppError;
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+const SHT_NOTE: u32 = 7;
+const NT_GNU_BUILD_ID: u32 = 3;
+
+/// Whether `path` is an ELF file.
+pub fn is_elf(path: &Path) -> Result<bool, AppError> {
+    let mut magic = [0; 4];
+    let read = File::open(path)
+        .and_then(|file| file.take(4).read(&mut magic))
+        .map_err(|e| app_err!("error reading {}: {}", path.display(), e))?;
+    Ok(read == 4 && magic == *b"\x7fELF")
+}
+
+/// The hex encoded build-id of an ELF file, `None` if it has none or is no ELF file.
+pub fn build_id(path: &Path) -> Result<Option<String>, AppError> {
+    if !is_elf(path)? {
+        return Ok(None);
+    }
+    let mut file = File::open(path)?;
+    match read_build_id(&mut file) {
+        // the headers point past the end of the file
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        result => result.map_err(|e| app_err!("error reading {}: {}", path.display(), e)),
+    }
+}
+
+fn read_build_id(file: &mut File) -> io::Result<Option<String>> {
+    let mut ident = [0; 64];
+    file.read_exact(&mut ident)?;
+    let elf = Elf {
+        is_64: ident[4] == 2,
+        is_le: ident[5] == 1,
+    };
+    let (offset, entry_size, count) = if elf.is_64 {
+        (
+            elf.u64(&ident[0x28..]),
+            elf.u16(&ident[0x3a..]),
+            elf.u16(&ident[0x3c..]),
+        )
+    } else {
+        (
+            elf.u32(&ident[0x20..]) as u64,
+            elf.u16(&ident[0x2e..]),
+            elf.u16(&ident[0x30..]),
+        )
+    };
+
+    let min_entry_size = if elf.is_64 { 0x40 } else { 0x28 };
+    let table_size = entry_size as u64 * count as u64;
+    if entry_size < min_entry_size || offset.saturating_add(table_size) > file.metadata()?.len() {
+        return Ok(None);
+    }
+    let mut sections = vec![0; table_size as usize];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut sections)?;
+    for section in sections.chunks(entry_size as usize) {
+        if elf.u32(&section[4..]) != SHT_NOTE {
+            continue;
+        }
+        let (offset, size) = if elf.is_64 {
+            (elf.u64(&section[0x18..]), elf.u64(&section[0x20..]))
+        } else {
+            (
+                elf.u32(&section[0x10..]) as u64,
+                elf.u32(&section[0x14..]) as u64,
+            )
+        };
+        let mut notes = Vec::new();
+        file.seek(SeekFrom::Start(offset))?;
+        file.take(size).read_to_end(&mut notes)?;
+        if let Some(build_id) = elf.find_build_id(&notes) {
+            return Ok(Some(hex::encode(build_id)));
+        }
+    }
+    Ok(None)
+}
+
+/// Class and byte order of an ELF file.
+struct Elf {
+    is_64: bool,
+    is_le: bool,
+}
+
+impl Elf {
+    fn u16(&self, raw: &[u8]) -> u16 {
+        let raw = [raw[0], raw[1]];
+        if self.is_le {
+            u16::from_le_bytes(raw)
+        } else {
+            u16::from_be_bytes(raw)
+        }
+    }
+
+    fn u32(&self, raw: &[u8]) -> u32 {
+        let raw = [raw[0], raw[1], raw[2], raw[3]];
+        if self.is_le {
+            u32::from_le_bytes(raw)
+        } else {
+            u32::from_be_bytes(raw)
+        }
+    }
+
+    fn u64(&self, raw: &[u8]) -> u64 {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&raw[..8]);
+        if self.is_le {
+            u64::from_le_bytes(bytes)
+        } else {
+            u64::from_be_bytes(bytes)
+        }
+    }
+
+    /// The descriptor of the GNU build-id note among `notes`.
+    fn find_build_id<'a>(&self, mut notes: &'a [u8]) -> Option<&'a [u8]> {
+        let align = |len: usize| (len + 3) & !3;
+        while notes.len() >= 12 {
+            let name_size = self.u32(notes) as usize;
+            let desc_size = self.u32(&notes[4..]) as usize;
+            let kind = self.u32(&notes[8..]);
+            let desc_start = 12 + align(name_size);
+            let end = desc_start + align(desc_size);
+            if notes.len() < desc_start + desc_size {
+                return None;
+            }
+            if kind == NT_GNU_BUILD_ID && &notes[12..12 + name_size] == b"GNU\0" {
+                return Some(&notes[desc_start..desc_start + desc_size]);
+            }
+            notes = &notes[end.min(notes.len())..];
+        }
+        None
+    }
+}
@@ -0,0 +1,148 @@
+use crate::cli;
+use crate::metadata::{FileInfo, PackageInfo};
+use crate::package::Package;
+use crate::payload;
+use crate::AppError;
+
+use std::collections::HashMap;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFLNK: u32 = 0o120000;
+const S_IFREG: u32 = 0o100000;
+
+/// Unpack the payload of a package into a directory, like `rpm2cpio | cpio -idm`.
+pub fn extract(matches: &clap::ArgMatches) -> Result<(), AppError> {
+    let path = matches.value_of(cli::PACKAGE_ARG).unwrap();
+    let target = Path::new(matches.value_of(cli::DIRECTORY_ARG).unwrap());
+    let pkg = Package::open(path)?;
+    let info = PackageInfo::from_header(&pkg.header);
+    let files: HashMap<&str, &FileInfo> = info.files.iter().map(|f| (f.path.as_str(), f)).collect();
+
+    std::fs::create_dir_all(target)
+        .map_err(|e| app_err!("unable to create directory {}: {}", target.display(), e))?;
+
+    // directory modes are applied last, a read only directory could not be filled otherwise
+    let mut directories = Vec::new();
+    let archive = payload::decoder(pkg.payload.reader()?)?;
+    payload::for_each_entry(archive, &info.files, |entry, content| {
+        let archive_path = entry.path.trim_start_matches('.');
+        let dest = destination(target, archive_path)?;
+        let file = files.get(archive_path);
+        // the header is authoritative, older builders left mode and mtime in the archive empty
+        let mode = file.map(|f| f.mode as u32).unwrap_or(entry.mode);
+        let mtime = file.map(|f| f.mtime).unwrap_or(entry.mtime as u64);
+
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        match mode & S_IFMT {
+            S_IFDIR => {
+                refuse_symlink(&dest, archive_path)?;
+                std::fs::create_dir_all(&dest)?;
+                directories.push((dest, mode, mtime));
+                return Ok(());
+            }
+            S_IFLNK => {
+                let link_to = match file {
+                    Some(f) if !f.link_to.is_empty() => f.link_to.clone(),
+                    _ => {
+                        let mut link_to = String::new();
+                        content.read_to_string(&mut link_to)?;
+                        link_to
+                    }
+                };
+                symlink(&link_to, &dest)?;
+                return Ok(());
+            }
+            S_IFREG | 0 => {
+                refuse_symlink(&dest, archive_path)?;
+                io::copy(content, &mut std::fs::File::create(&dest)?)?;
+            }
+            _ => return Err(app_err!("unsupported file type of {}", archive_path)),
+        }
+        set_metadata(&dest, mode, mtime)
+            .map_err(|e| app_err!("unable to set metadata of {}: {}", dest.display(), e))
+    })?;
+
+    for (dest, mode, mtime) in directories.into_iter().rev() {
+        set_metadata(&dest, mode, mtime)
+            .map_err(|e| app_err!("unable to set metadata of {}: {}", dest.display(), e))?;
+    }
+    Ok(())
+}
+
+/// Resolve an archive path below `target`, refusing anything that would escape it.
+///
+/// Directories on the way must not be symlinks, whether they come from the payload or were
+/// already there, as writing through them could end up anywhere.
+fn destination(target: &Path, archive_path: &str) -> Result<PathBuf, AppError> {
+    let mut dest = target.to_path_buf();
+    for component in Path::new(archive_path).components() {
+        match component {
+            Component::Normal(part) => {
+                // the target directory itself is up to the user
+                if dest != target {
+                    refuse_symlink(&dest, archive_path)?;
+                }
+                dest.push(part)
+            }
+            Component::RootDir | Component::CurDir => {}
+            _ => {
+                return Err(app_err!(
+                    "refusing to extract {} outside of the target directory",
+                    archive_path
+                ))
+            }
+        }
+    }
+    if dest == target {
+        return Err(app_err!("invalid path {} in payload", archive_path));
+    }
+    Ok(dest)
+}
+
+/// Fail if `path` below the target directory is a symlink.
+fn refuse_symlink(path: &Path, archive_path: &str) -> Result<(), AppError> {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_symlink() => Err(app_err!(
+            "refusing to extract {} through the symlink {}",
+            archive_path,
+            path.display()
+        )),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(unix)]
+fn symlink(link_to: &str, dest: &Path) -> Result<(), AppError> {
+    if std::fs::symlink_metadata(dest).is_ok() {
+        std::fs::remove_file(dest)?;
+    }
+    std::os::unix::fs::symlink(link_to, dest)
+        .map_err(|e| app_err!("unable to create symlink {}: {}", dest.display(), e))
+}
+
+#[cfg(not(unix))]
+fn symlink(_link_to: &str, dest: &Path) -> Result<(), AppError> {
+    Err(app_err!(
+        "unable to create symlink {}: symlinks are not supported on this platform",
+        dest.display()
+    ))
+}
+
+fn set_metadata(dest: &Path, mode: u32, mtime: u64) -> std::io::Result<()> {
+    let file = std::fs::File::open(dest)?;
+    let modified = std::time::UNIX_EPOCH + std::time::Duration::from_secs(mtime);
+    file.set_modified(modified)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(std::fs::Permissions::from_mode(mode & 0o7777))?;
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+    Ok(())
+}
@@ -0,0 +1,332 @@
+//! Low level access to rpm headers.
+//!
+//! `rpm-rs` keeps the header entries and their serialization private and only exposes
+//! a handful of getters. Everything that needs to look at arbitrary tags or has to
+//! rewrite a header (e.g. to replace the signature) goes through this module instead.
+
+use crate::AppError;
+
+const HEADER_MAGIC: [u8; 3] = [0x8e, 0xad, 0xe8];
+
+// tags describing the header regions. They are recreated on serialization.
+const HEADER_IMAGE: u32 = 61;
+const HEADER_SIGNATURES: u32 = 62;
+const HEADER_IMMUTABLE: u32 = 63;
+
+/// Numeric value of a header tag.
+pub trait Tag: Copy {
+    fn id(self) -> u32;
+}
+
+impl Tag for rpm::IndexTag {
+    fn id(self) -> u32 {
+        self as u32
+    }
+}
+
+impl Tag for rpm::IndexSignatureTag {
+    fn id(self) -> u32 {
+        self as u32
+    }
+}
+
+impl Tag for u32 {
+    fn id(self) -> u32 {
+        self
+    }
+}
+
+/// The data of a single header entry.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Char(Vec<u8>),
+    Int8(Vec<u8>),
+    Int16(Vec<u16>),
+    Int32(Vec<u32>),
+    Int64(Vec<u64>),
+    String(String),
+    Bin(Vec<u8>),
+    StringArray(Vec<String>),
+    I18NString(Vec<String>),
+}
+
+impl Value {
+    fn type_id(&self) -> u32 {
+        match self {
+            Value::Null => 0,
+            Value::Char(_) => 1,
+            Value::Int8(_) => 2,
+            Value::Int16(_) => 3,
+            Value::Int32(_) => 4,
+            Value::Int64(_) => 5,
+            Value::String(_) => 6,
+            Value::Bin(_) => 7,
+            Value::StringArray(_) => 8,
+            Value::I18NString(_) => 9,
+        }
+    }
+
+    fn count(&self) -> u32 {
+        let count = match self {
+            Value::Null => 0,
+            Value::Char(v) | Value::Int8(v) | Value::Bin(v) => v.len(),
+            Value::Int16(v) => v.len(),
+            Value::Int32(v) => v.len(),
+            Value::Int64(v) => v.len(),
+            Value::String(_) => 1,
+            Value::StringArray(v) | Value::I18NString(v) => v.len(),
+        };
+        count as u32
+    }
+
+    fn alignment(&self) -> usize {
+        match self {
+            Value::Int16(_) => 2,
+            Value::Int32(_) => 4,
+            Value::Int64(_) => 8,
+            _ => 1,
+        }
+    }
+
+    fn write_to(&self, store: &mut Vec<u8>) {
+        match self {
+            Value::Null => {}
+            Value::Char(v) | Value::Int8(v) | Value::Bin(v) => store.extend_from_slice(v),
+            Value::Int16(v) => v
+                .iter()
+                .for_each(|i| store.extend_from_slice(&i.to_be_bytes())),
+            Value::Int32(v) => v
+                .iter()
+                .for_each(|i| store.extend_from_slice(&i.to_be_bytes())),
+            Value::Int64(v) => v
+                .iter()
+                .for_each(|i| store.extend_from_slice(&i.to_be_bytes())),
+            Value::String(s) => {
+                store.extend_from_slice(s.as_bytes());
+                store.push(0);
+            }
+            Value::StringArray(v) | Value::I18NString(v) => {
+                for s in v {
+                    store.extend_from_slice(s.as_bytes());
+                    store.push(0);
+                }
+            }
+        }
+    }
+
+    fn parse(type_id: u32, count: usize, data: &[u8]) -> Result<Value, AppError> {
+        let fixed = |size: usize| -> Result<&[u8], AppError> {
+            data.get(..size * count)
+                .ok_or_else(|| app_err!("header entry exceeds the header store"))
+        };
+        let value = match type_id {
+            0 => Value::Null,
+            1 => Value::Char(fixed(1)?.to_vec()),
+            2 => Value::Int8(fixed(1)?.to_vec()),
+            3 => Value::Int16(
+                fixed(2)?
+                    .chunks(2)
+                    .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                    .collect(),
+            ),
+            4 => Value::Int32(
+                fixed(4)?
+                    .chunks(4)
+                    .map(|c| u32::from_be_bytes([c[0], c[1], c[2], c[3]]))
+                    .collect(),
+            ),
+            5 => Value::Int64(
+                fixed(8)?
+                    .chunks(8)
+                    .map(|c| {
+                        let mut bytes = [0u8; 8];
+                        bytes.copy_from_slice(c);
+                        u64::from_be_bytes(bytes)
+                    })
+                    .collect(),
+            ),
+            6 => Value::String(parse_strings(data, 1)?.remove(0)),
+            7 => Value::Bin(fixed(1)?.to_vec()),
+            8 => Value::StringArray(parse_strings(data, count)?),
+            9 => Value::I18NString(parse_strings(data, count)?),
+            _ => return Err(app_err!("unknown header data type {}", type_id)),
+        };
+        Ok(value)
+    }
+}
+
+fn parse_strings(mut data: &[u8], count: usize) -> Result<Vec<String>, AppError> {
+    let mut strings = Vec::with_capacity(count);
+    for _ in 0..count {
+        let end = data
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or_else(|| app_err!("unterminated string in header store"))?;
+        strings.push(String::from_utf8_lossy(&data[..end]).to_string());
+        data = &data[end + 1..];
+    }
+    Ok(strings)
+}
+
+/// A single tag and its data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub tag: u32,
+    pub value: Value,
+}
+
+/// An rpm header, either the signature header or the main header of a package.
+///
+/// Region tags are not part of the entries, they are dropped while parsing and
+/// recreated by [`to_bytes`](Header::to_bytes).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Header {
+    pub entries: Vec<Entry>,
+}
+
+impl Header {
+    /// Parse a header from the beginning of `input`.
+    ///
+    /// Returns the header and the number of bytes it occupies, excluding any padding.
+    pub fn parse(input: &[u8]) -> Result<(Header, usize), AppError> {
+        if input.len() < 16 || input[..3] != HEADER_MAGIC {
+            return Err(app_err!("invalid header magic"));
+        }
+        let num_entries = be_u32(&input[8..12]) as usize;
+        let store_size = be_u32(&input[12..16]) as usize;
+        let index_end = 16 + num_entries * 16;
+        let total = index_end + store_size;
+        if input.len() < total {
+            return Err(app_err!("header is truncated"));
+        }
+        let store = &input[index_end..total];
+
+        let mut entries = Vec::with_capacity(num_entries);
+        for raw in input[16..index_end].chunks(16) {
+            let tag = be_u32(&raw[0..4]);
+            if tag == HEADER_IMAGE || tag == HEADER_SIGNATURES || tag == HEADER_IMMUTABLE {
+                continue;
+            }
+            let type_id = be_u32(&raw[4..8]);
+            let offset = be_u32(&raw[8..12]) as usize;
+            let count = be_u32(&raw[12..16]) as usize;
+            let data = store
+                .get(offset..)
+                .ok_or_else(|| app_err!("header entry {} points outside of the store", tag))?;
+            entries.push(Entry {
+                tag,
+                value: Value::parse(type_id, count, data)?,
+            });
+        }
+        Ok((Header { entries }, total))
+    }
+
+    /// Serialize the header including the region tag.
+    ///
+    /// Entries are written sorted by tag, the way rpm itself writes headers.
+    pub fn to_bytes(&self, signature: bool) -> Vec<u8> {
+        let region_tag = if signature {
+            HEADER_SIGNATURES
+        } else {
+            HEADER_IMMUTABLE
+        };
+        let mut entries: Vec<&Entry> = self.entries.iter().collect();
+        entries.sort_by_key(|e| e.tag);
+
+        let mut index = Vec::with_capacity((entries.len() + 1) * 16);
+        let mut store = Vec::new();
+        for entry in entries.iter() {
+            while store.len() % entry.value.alignment() != 0 {
+                store.push(0);
+            }
+            index.extend_from_slice(&entry.tag.to_be_bytes());
+            index.extend_from_slice(&entry.value.type_id().to_be_bytes());
+            index.extend_from_slice(&(store.len() as u32).to_be_bytes());
+            index.extend_from_slice(&entry.value.count().to_be_bytes());
+            entry.value.write_to(&mut store);
+        }
+
+        // the region trailer points back to the start of the index
+        let num_entries = entries.len() as u32 + 1;
+        let trailer_offset = store.len() as u32;
+        store.extend_from_slice(&region_tag.to_be_bytes());
+        store.extend_from_slice(&7u32.to_be_bytes());
+        store.extend_from_slice(&(-(num_entries as i32 * 16)).to_be_bytes());
+        store.extend_from_slice(&16u32.to_be_bytes());
+
+        let mut out = Vec::with_capacity(16 + index.len() + 16 + store.len());
+        out.extend_from_slice(&HEADER_MAGIC);
+        out.push(1);
+        out.extend_from_slice(&[0; 4]);
+        out.extend_from_slice(&num_entries.to_be_bytes());
+        out.extend_from_slice(&(store.len() as u32).to_be_bytes());
+        out.extend_from_slice(&region_tag.to_be_bytes());
+        out.extend_from_slice(&7u32.to_be_bytes());
+        out.extend_from_slice(&trailer_offset.to_be_bytes());
+        out.extend_from_slice(&16u32.to_be_bytes());
+        out.extend_from_slice(&index);
+        out.extend_from_slice(&store);
+        out
+    }
+
+    pub fn get<T: Tag>(&self, tag: T) -> Option<&Value> {
+        let tag = tag.id();
+        self.entries.iter().find(|e| e.tag == tag).map(|e| &e.value)
+    }
+
+    /// Replace the value of `tag` or add it if it is not present yet.
+    pub fn set<T: Tag>(&mut self, tag: T, value: Value) {
+        let tag = tag.id();
+        match self.entries.iter_mut().find(|e| e.tag == tag) {
+            Some(entry) => entry.value = value,
+            None => self.entries.push(Entry { tag, value }),
+        }
+    }
+
+    pub fn remove<T: Tag>(&mut self, tag: T) {
+        let tag = tag.id();
+        self.entries.retain(|e| e.tag != tag);
+    }
+
+    pub fn string<T: Tag>(&self, tag: T) -> Option<&str> {
+        match self.get(tag)? {
+            Value::String(s) => Some(s),
+            Value::StringArray(v) | Value::I18NString(v) => v.first().map(|s| s.as_str()),
+            _ => None,
+        }
+    }
+
+    pub fn strings<T: Tag>(&self, tag: T) -> &[String] {
+        match self.get(tag) {
+            Some(Value::StringArray(v)) | Some(Value::I18NString(v)) => v,
+            _ => &[],
+        }
+    }
+
+    pub fn int<T: Tag>(&self, tag: T) -> Option<u64> {
+        self.ints(tag).first().copied()
+    }
+
+    /// Integer values of `tag`, regardless of their width.
+    pub fn ints<T: Tag>(&self, tag: T) -> Vec<u64> {
+        match self.get(tag) {
+            Some(Value::Char(v)) | Some(Value::Int8(v)) => v.iter().map(|i| *i as u64).collect(),
+            Some(Value::Int16(v)) => v.iter().map(|i| *i as u64).collect(),
+            Some(Value::Int32(v)) => v.iter().map(|i| *i as u64).collect(),
+            Some(Value::Int64(v)) => v.clone(),
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn bin<T: Tag>(&self, tag: T) -> Option<&[u8]> {
+        match self.get(tag)? {
+            Value::Bin(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+fn be_u32(raw: &[u8]) -> u32 {
+    u32::from_be_bytes([raw[0], raw[1], raw[2], raw[3]])
+}
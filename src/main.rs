@@ -1,12 +1,9 @@
-use chrono;
 extern crate clap;
 
 use regex::Regex;
-use rpm;
+use std::collections::{BTreeMap, BTreeSet};
 use std::path::{Path, PathBuf};
-use std::str::FromStr;
-
-mod cli;
+use subpackage::PackageArgs;
 
 macro_rules! app_err {
     ($format:expr $(, $x:expr )*) => {
@@ -14,73 +11,217 @@ macro_rules! app_err {
     };
 }
 
+mod arch;
+mod build_id;
+mod cli;
+mod compression;
+mod debuginfo;
+mod description;
+mod diff;
+mod digest;
+mod elf;
+mod extract;
+mod header;
+mod metadata;
+mod output;
+mod package;
+mod payload;
+mod query;
+mod repo;
+mod reproducible;
+mod sign;
+mod signing;
+mod spec;
+mod subpackage;
+mod verify;
+
 fn main() -> Result<(), AppError> {
     let matches = cli::build_cli().get_matches();
-    let name = matches.value_of(cli::NAME_ARG).unwrap();
-    let version = matches.value_of(cli::VERSION_ARG).unwrap();
-    let license = matches.value_of(cli::LICENSE_ARG).unwrap();
-    let arch = matches.value_of(cli::ARCH_ARG).unwrap();
-    let description = matches.value_of(cli::DESC_ARG).unwrap();
-    let epoch: i32 = matches
-        .value_of(cli::EPOCH_ARG)
-        .unwrap()
-        .parse()
-        .map_err(|_e| app_err!("unable to convert provided epoch value to integer"))?;
+    match matches.subcommand() {
+        (cli::SIGN_SUBCOMMAND, Some(sub_matches)) => sign::sign(sub_matches),
+        (cli::VERIFY_SUBCOMMAND, Some(sub_matches)) => verify::verify(sub_matches),
+        (cli::QUERY_SUBCOMMAND, Some(sub_matches)) => query::query(sub_matches),
+        (cli::EXTRACT_SUBCOMMAND, Some(sub_matches)) => extract::extract(sub_matches),
+        (cli::DIFF_SUBCOMMAND, Some(sub_matches)) => diff::diff(sub_matches),
+        (cli::REPO_SUBCOMMAND, Some(sub_matches)) => repo::repo(sub_matches),
+        _ => build(&matches),
+    }
+}
 
-    let release = matches.value_of(cli::RELEASE_ARG).unwrap();
+fn build(matches: &clap::ArgMatches) -> Result<(), AppError> {
+    let packages = subpackage::packages(matches)?;
+    let mut arch_files = Vec::new();
+    for package in &packages {
+        arch_files.push(arch_files_of(package)?);
+    }
+    let mut arches: Vec<String> = arch_files
+        .iter()
+        .flat_map(|files| files.keys().cloned())
+        .collect();
+    arches.sort();
+    arches.dedup();
+    if arches.is_empty() {
+        arches.push(matches.value_of(cli::ARCH_ARG).unwrap().to_string());
+    } else if matches.occurrences_of(cli::ARCH_ARG) > 0 {
+        return Err(app_err!(
+            "--{} can not be used together with --{}",
+            cli::ARCH_ARG,
+            cli::EXEC_FILE_FOR_ARG
+        ));
+    }
+    if matches.is_present(cli::EMIT_SPEC_ARG) {
+        for arch in &arches {
+            arch::validate(arch)?;
+        }
+        print!("{}", spec::spec(&packages, &arches)?);
+        return Ok(());
+    }
 
-    let output_path = match matches.value_of(cli::OUT_ARG) {
-        Some(p) => p.to_string(),
-        None => format!("./{}.rpm", name),
+    let source_date_epoch = reproducible::source_date_epoch(matches)?;
+    let signer = match matches.value_of(cli::SIGN_WITH_PGP_ASC_ARG) {
+        Some(signing_key_path) => {
+            let passphrase = signing::read_passphrase(matches)?;
+            let signer = signing::load_signer(signing_key_path, passphrase)?;
+            Some(signer.source_date_epoch(source_date_epoch))
+        }
+        None => None,
     };
 
-    let compressor = rpm::Compressor::from_str(matches.value_of(cli::COMPRESSION_ARG).unwrap())?;
-    let mut builder =
-        rpm::RPMBuilder::new(name, version, license, arch, description).compression(compressor);
-
-    let files = matches
-        .values_of(cli::FILE_ARG)
-        .map(|v| v.collect())
-        .unwrap_or(Vec::new());
-
-    for (src, options) in parse_file_options(files)? {
-        builder = builder
-            .with_file(src, options)
-            .map_err(|e| app_err!("error adding regular file {}: {}", src, e))?;
+    let mut builds = Vec::new();
+    for arch in &arches {
+        for (package, files) in packages.iter().zip(arch_files.iter_mut()) {
+            let output_path = output::output_path(matches, &package.name, arch)?;
+            if builds.iter().any(|(_, _, _, path)| *path == output_path) {
+                return Err(app_err!(
+                    "several packages would be written to {}, add {{name}} and {{arch}} to --{}",
+                    output_path.display(),
+                    cli::OUT_ARG
+                ));
+            }
+            let files = files.remove(arch).unwrap_or_default();
+            builds.push((package, arch, files, output_path));
+        }
+    }
+    let source_rpm_path = if matches.is_present(cli::SRPM_ARG) {
+        let path = output::source_rpm_path(matches)?;
+        if builds.iter().any(|(_, _, _, other)| *other == path) {
+            return Err(app_err!(
+                "the source rpm would be written to {} as well, add {{arch}} to --{}",
+                path.display(),
+                cli::OUT_ARG
+            ));
+        }
+        Some(path)
+    } else {
+        None
+    };
+    if let Some(dir) = matches.value_of(cli::OUT_DIR_ARG) {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| app_err!("unable to create output directory {}: {}", dir, e))?;
     }
 
-    builder = builder
-        .release(release)
-        .epoch(epoch);
-
-    let files = matches
-        .values_of(cli::EXEC_FILE_ARG)
-        .map(|v| v.collect())
-        .unwrap_or(Vec::new());
+    // build-id links added so far, by architecture
+    let mut linked: BTreeMap<&str, BTreeSet<String>> = BTreeMap::new();
+    for (package, arch, files, output_path) in builds {
+        let linked = linked.entry(arch.as_str()).or_default();
+        let mut files = package_files(package, arch, files, linked)?;
+        // keeps the stripped files until the package is written
+        let mut _debug_dir = None;
+        if matches.is_present(cli::DEBUGINFO_ARG) && arch != arch::NOARCH {
+            let dir = tempfile::tempdir()
+                .map_err(|e| app_err!("unable to create temporary directory: {}", e))?;
+            let debug_files = debuginfo::split(&mut files, dir.path(), linked)?;
+            if !debug_files.is_empty() {
+                let debug_package = package.debuginfo();
+                let debug_path = output::output_path(matches, &debug_package.name, arch)?;
+                build_package(
+                    &debug_package,
+                    arch,
+                    debug_files,
+                    &debug_path,
+                    signer.as_ref(),
+                    source_date_epoch,
+                )?;
+            }
+            _debug_dir = Some(dir);
+        }
+        build_package(
+            package,
+            arch,
+            files,
+            &output_path,
+            signer.as_ref(),
+            source_date_epoch,
+        )?;
+    }
 
-    for (src, options) in parse_file_options(files)? {
-        builder = builder
-            .with_file(src, options.mode(0o100755))
-            .map_err(|e| app_err!("error adding executable file {}: {}", src, e))?;
+    if let Some(source_rpm_path) = source_rpm_path {
+        let main = &packages[0];
+        let dir = tempfile::tempdir()
+            .map_err(|e| app_err!("unable to create temporary directory: {}", e))?;
+        let spec_name = spec::spec_name(main);
+        let spec_path = dir.path().join(&spec_name);
+        std::fs::write(&spec_path, spec::spec(&packages, &arches)?)
+            .map_err(|e| app_err!("unable to write {}: {}", spec_path.display(), e))?;
+        let mut files = vec![FileEntry {
+            source: spec_path,
+            dest: spec_name,
+            kind: FileKind::Spec,
+        }];
+        files.extend(spec::sources(&packages)?);
+        sort_files(&mut files)?;
+        build_package(
+            &main.source_package(),
+            &arches[0],
+            files,
+            &source_rpm_path,
+            signer.as_ref(),
+            source_date_epoch,
+        )?;
     }
+    Ok(())
+}
 
-    let files = matches
-        .values_of(cli::CONFIG_FILE_ARG)
-        .map(|v| v.collect())
-        .unwrap_or(Vec::new());
-    for (src, options) in parse_file_options(files)? {
-        builder = builder
-            .with_file(src, options.is_config())
-            .map_err(|e| app_err!("error adding config file {}: {}", src, e))?;
+/// Collect the executables given with `--exec-file-for` for `package`, grouped by architecture.
+fn arch_files_of(package: &PackageArgs) -> Result<BTreeMap<String, Vec<FileEntry>>, AppError> {
+    let raw = package.values_of(cli::EXEC_FILE_FOR_ARG);
+    let mut arch_files: BTreeMap<String, Vec<FileEntry>> = BTreeMap::new();
+    for pair in raw.chunks(2) {
+        let (arch, raw_file) = (pair[0], pair[1]);
+        for (src, dest) in parse_file_options(vec![raw_file])? {
+            arch_files
+                .entry(arch.to_string())
+                .or_default()
+                .push(FileEntry {
+                    source: PathBuf::from(src),
+                    dest: file_dest(dest),
+                    kind: FileKind::Executable,
+                });
+        }
     }
+    Ok(arch_files)
+}
 
-    let dirs = matches
-        .values_of(cli::DIR_ARG)
-        .map(|v| v.collect())
-        .unwrap_or(Vec::new());
+/// The files given on the command line for `package`, with directories walked.
+fn command_line_files(package: &PackageArgs) -> Result<Vec<FileEntry>, AppError> {
+    let mut files = Vec::new();
+    for (arg, kind) in [
+        (cli::FILE_ARG, FileKind::Regular),
+        (cli::EXEC_FILE_ARG, FileKind::Executable),
+        (cli::CONFIG_FILE_ARG, FileKind::Config),
+        (cli::DOC_FILE_ARG, FileKind::Doc),
+    ] {
+        for (src, dest) in parse_file_options(package.values_of(arg))? {
+            files.push(FileEntry {
+                source: PathBuf::from(src),
+                dest: file_dest(dest),
+                kind,
+            });
+        }
+    }
 
-    for dir in dirs {
-        let parts: Vec<&str> = dir.split(":").collect();
+    for dir in package.values_of(cli::DIR_ARG) {
+        let parts: Vec<&str> = dir.split(':').collect();
         if parts.len() != 2 {
             return Err(app_err!(
                 "invalid file argument:{} it needs to be of the form <source-path>:<dest-path>",
@@ -89,40 +230,98 @@ fn main() -> Result<(), AppError> {
         }
         let dir = parts[0];
         let target = PathBuf::from(parts[1]);
-        builder = add_dir(dir, &target, builder)
+        add_dir(dir, &target, &mut files)
             .map_err(|e| app_err!("error adding dir {}: {}", dir, e))?;
     }
+    Ok(files)
+}
 
-    let files = matches
-        .values_of(cli::DOC_FILE_ARG)
-        .map(|v| v.collect())
-        .unwrap_or(Vec::new());
-    for (src, options) in parse_file_options(files)? {
-        builder = builder
-            .with_file(src, options.is_doc())
-            .map_err(|e| app_err!("error adding doc file {}: {}", src, e))?;
+/// The files given on the command line for `package` together with `files`, sorted by their
+/// destination and checked against `arch`.
+///
+/// Build-id links already in `linked`, which holds the ones of the packages built before for
+/// `arch`, are left out.
+fn package_files(
+    package: &PackageArgs,
+    arch: &str,
+    mut files: Vec<FileEntry>,
+    linked: &mut BTreeSet<String>,
+) -> Result<Vec<FileEntry>, AppError> {
+    arch::validate(arch)?;
+    files.extend(command_line_files(package)?);
+    if !package.matches.is_present(cli::NO_BUILD_ID_LINKS_ARG) {
+        let links = build_id::links(&files, linked)?;
+        files.extend(links);
     }
 
-    if let Some(scriptlet) = read_scriptlet(cli::PRE_INSTALL_SCRIPTLET_ARG, &matches)? {
+    sort_files(&mut files)?;
+    arch::check_files(arch, &files)?;
+    Ok(files)
+}
+
+/// Sort `files` by their destination, no two files may have the same one.
+fn sort_files(files: &mut [FileEntry]) -> Result<(), AppError> {
+    // the package content must not depend on the order of the arguments
+    files.sort_by(|a, b| a.dest.cmp(&b.dest));
+    if let Some(pair) = files.windows(2).find(|pair| pair[0].dest == pair[1].dest) {
+        return Err(app_err!(
+            "{} and {} are both added as {}",
+            pair[0].source.display(),
+            pair[1].source.display(),
+            pair[0].dest
+        ));
+    }
+    Ok(())
+}
+
+/// Build `package` for `arch` with `files`.
+fn build_package(
+    package: &PackageArgs,
+    arch: &str,
+    files: Vec<FileEntry>,
+    output_path: &Path,
+    signer: Option<&signing::Signer>,
+    source_date_epoch: Option<u64>,
+) -> Result<(), AppError> {
+    let matches = package.matches;
+    let name = package.name.as_str();
+    let version = matches.value_of(cli::VERSION_ARG).unwrap();
+    let license = matches.value_of(cli::LICENSE_ARG).unwrap();
+    let epoch: i32 = matches
+        .value_of(cli::EPOCH_ARG)
+        .unwrap()
+        .parse()
+        .map_err(|_e| app_err!("unable to convert provided epoch value to integer"))?;
+
+    let release = matches.value_of(cli::RELEASE_ARG).unwrap();
+
+    let compression = compression::compression(matches)?;
+    let file_digest = digest::algorithm(matches.value_of(cli::FILE_DIGEST_ARG).unwrap()).unwrap();
+    // rpm-rs only builds the header, files are streamed into the payload separately
+    let mut builder = rpm::RPMBuilder::new(name, version, license, arch, &package.description);
+
+    builder = builder.release(release).epoch(epoch);
+
+    if let Some(scriptlet) = read_scriptlet(cli::PRE_INSTALL_SCRIPTLET_ARG, package)? {
         builder = builder.pre_install_script(scriptlet);
     }
-    if let Some(scriptlet) = read_scriptlet(cli::POST_INSTALL_SCRIPTLET_ARG, &matches)? {
+    if let Some(scriptlet) = read_scriptlet(cli::POST_INSTALL_SCRIPTLET_ARG, package)? {
         builder = builder.post_install_script(scriptlet);
     }
-    if let Some(scriptlet) = read_scriptlet(cli::PRE_UNINSTALL_SCRIPTLET_ARG, &matches)? {
+    if let Some(scriptlet) = read_scriptlet(cli::PRE_UNINSTALL_SCRIPTLET_ARG, package)? {
         builder = builder.pre_uninstall_script(scriptlet);
     }
-    if let Some(scriptlet) = read_scriptlet(cli::POST_UNINSTALL_SCRIPTLET_ARG, &matches)? {
+    if let Some(scriptlet) = read_scriptlet(cli::POST_UNINSTALL_SCRIPTLET_ARG, package)? {
         builder = builder.post_uninstall_script(scriptlet);
     }
 
-    let raw_changelog = matches
+    let raw_changelog: Vec<&str> = matches
         .values_of(cli::CHANGELOG_ARG)
         .map(|v| v.collect())
-        .unwrap_or(Vec::new());
+        .unwrap_or_default();
 
     for raw_entry in raw_changelog {
-        let parts: Vec<&str> = raw_entry.split(":").collect();
+        let parts: Vec<&str> = raw_entry.split(':').collect();
         if parts.len() != 3 {
             return Err(app_err!(
                     "invalid file argument:{} it needs to be of the form <author>:<content>:<yyyy-mm-dd>",
@@ -148,83 +347,258 @@ fn main() -> Result<(), AppError> {
 
     let re = Regex::new(r"^([a-zA-Z0-9\-\._]+)(\s*(>=|>|=|<=|<)(.+))?$").unwrap();
 
-    let requires = matches
-        .values_of(cli::REQUIRES_ARG)
-        .map(|v| v.collect())
-        .unwrap_or(Vec::new());
+    let requires = package.values_of(cli::REQUIRES_ARG);
 
     for req in requires {
         let dependency = parse_dependency(&re, req)?;
         builder = builder.requires(dependency);
     }
 
-    let obsoletes = matches
-        .values_of(cli::OBSOLETES_ARG)
-        .map(|v| v.collect())
-        .unwrap_or(Vec::new());
+    let obsoletes = package.values_of(cli::OBSOLETES_ARG);
 
     for item in obsoletes {
         let dependency = parse_dependency(&re, item)?;
         builder = builder.obsoletes(dependency);
     }
 
-    let conflicts = matches
-        .values_of(cli::CONFLICTS_ARG)
-        .map(|v| v.collect())
-        .unwrap_or(Vec::new());
+    let conflicts = package.values_of(cli::CONFLICTS_ARG);
 
     for item in conflicts {
         let dependency = parse_dependency(&re, item)?;
         builder = builder.conflicts(dependency);
     }
 
-    let provides = matches
-        .values_of(cli::PROVIDES_ARG)
-        .map(|v| v.collect())
-        .unwrap_or(Vec::new());
+    let provides = package.values_of(cli::PROVIDES_ARG);
 
+    let mut provide_names = Vec::new();
     for item in provides {
         let dependency = parse_dependency(&re, item)?;
         builder = builder.provides(dependency);
+        provide_names.push(re.captures(item).unwrap()[1].to_string());
     }
-
-    let pkg = if let Some(signing_key_path) = matches.value_of(cli::SIGN_WITH_PGP_ASC_ARG) {
-        let raw_key = std::fs::read(signing_key_path).map_err(|e| {
+    // rpm-rs adds these two after the explicit ones
+    provide_names.push(name.to_string());
+    provide_names.push(format!("{}({})", name, arch));
+
+    let mut pkg = package::Package::from_rpm(&builder.build()?)?;
+    if package.is_source {
+        // source rpms provide nothing, rpm tells them apart from binary ones by this tag
+        pkg.header.remove(rpm::IndexTag::RPMTAG_PROVIDENAME);
+        pkg.header.remove(rpm::IndexTag::RPMTAG_PROVIDEVERSION);
+        pkg.header.remove(rpm::IndexTag::RPMTAG_PROVIDEFLAGS);
+        pkg.header.set(
+            rpm::IndexTag::RPMTAG_SOURCEPACKAGE,
+            header::Value::Int32(vec![1]),
+        );
+    } else {
+        // rpm-rs leaves out the provide names of packages without files
+        pkg.header.set(
+            rpm::IndexTag::RPMTAG_PROVIDENAME,
+            header::Value::StringArray(provide_names),
+        );
+        if matches.is_present(cli::SRPM_ARG) {
+            let source_rpm = output::source_rpm_path(matches)?;
+            let file_name = source_rpm.file_name().unwrap().to_string_lossy();
+            pkg.header.set(
+                rpm::IndexTag::RPMTAG_SOURCERPM,
+                header::Value::String(file_name.to_string()),
+            );
+        }
+    }
+    write_payload(
+        &files,
+        compression,
+        file_digest,
+        output_path,
+        package.is_source,
+        &mut pkg,
+    )?;
+    set_metadata_tags(&mut pkg, matches, &package.summary);
+    reproducible::apply(&mut pkg, source_date_epoch);
+    pkg.update_header()?;
+    pkg.sign(signer)?;
+
+    let mut out_file = std::fs::File::create(output_path).map_err(|e| {
+        app_err!(
+            "unable to create output file {}: {}",
+            output_path.display(),
+            e
+        )
+    })?;
+    pkg.write(&mut std::io::BufWriter::new(&mut out_file))
+        .map_err(|e| {
             app_err!(
-                "unable to load private key file from path {}: {}",
-                signing_key_path,
+                "unable to write package to path {}: {}",
+                output_path.display(),
                 e
             )
         })?;
+    Ok(())
+}
 
-        let signer = rpm::signature::pgp::Signer::load_from_asc_bytes(&raw_key).map_err(|e| {
+/// Stream `files` into the compressed payload of `pkg` and add the tags describing them to its
+/// headers.
+///
+/// The payload is kept in a temporary file next to the output, so memory usage does not
+/// grow with the size of the package.
+fn write_payload(
+    files: &[FileEntry],
+    compression: compression::Compression,
+    file_digest: u64,
+    output_path: &Path,
+    source: bool,
+    pkg: &mut package::Package,
+) -> Result<(), AppError> {
+    let out_dir = match output_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let tmp_file = tempfile::tempfile_in(out_dir).map_err(|e| {
+        app_err!(
+            "unable to create temporary file in {}: {}",
+            out_dir.display(),
+            e
+        )
+    })?;
+    let mut payload =
+        payload::PayloadWriter::new(std::io::BufWriter::new(tmp_file), compression, file_digest)?;
+    if source {
+        // source rpms keep the spec file and the sources without a directory
+        payload = payload.relative_paths();
+    }
+    // rpm expects stripped entries for every file once a single one is larger than 4 GiB
+    let mut large_files = false;
+    for file in files
+        .iter()
+        .filter(|file| !matches!(file.kind, FileKind::Symlink))
+    {
+        let metadata = std::fs::metadata(&file.source)
+            .map_err(|e| app_err!("error reading {}: {}", file.source.display(), e))?;
+        large_files |= metadata.len() > u32::MAX as u64;
+    }
+    if large_files {
+        payload = payload.large_files();
+    }
+    for file in files {
+        match file.kind {
+            FileKind::Symlink => payload.add_symlink(&file.dest, &file.source.to_string_lossy()),
+            _ => payload.add_file(
+                &file.source,
+                &file.dest,
+                file.kind.mode(),
+                file.kind.flags(),
+            ),
+        }
+        .map_err(|e| {
             app_err!(
-                "unable to create signer from private key {}: {}",
-                signing_key_path,
+                "error adding {} {}: {}",
+                file.kind.description(),
+                file.source.display(),
                 e
             )
         })?;
-        builder.build_and_sign(signer)?
-    } else {
-        builder.build()?
-    };
-
-    let mut out_file = std::fs::File::create(&output_path)
-        .map_err(|e| app_err!("unable to create output file {}: {}", output_path, e))?;
-    pkg.write(&mut out_file)
-        .map_err(|e| app_err!("unable to write package to path {}: {}", output_path, e))?;
+    }
+    let (tmp_file, archive_size) = payload.finish(&mut pkg.header)?;
+    let tmp_file = tmp_file.into_inner().map_err(|e| e.into_error())?;
+    package::set_archive_size(&mut pkg.signature, archive_size);
+    pkg.payload = package::Payload::File(tmp_file, 0);
     Ok(())
 }
 
+/// Set the descriptive tags rpm-rs has no setters for.
+fn set_metadata_tags(pkg: &mut package::Package, matches: &clap::ArgMatches, summary: &str) {
+    use header::Value;
+    use rpm::IndexTag;
+
+    pkg.header
+        .set(IndexTag::RPMTAG_SUMMARY, Value::I18NString(vec![summary.to_string()]));
+    if let Some(group) = matches.value_of(cli::GROUP_ARG) {
+        pkg.header
+            .set(IndexTag::RPMTAG_GROUP, Value::I18NString(vec![group.to_string()]));
+    }
+    for (arg, tag) in [
+        (cli::URL_ARG, IndexTag::RPMTAG_URL),
+        (cli::VENDOR_ARG, IndexTag::RPMTAG_VENDOR),
+        (cli::PACKAGER_ARG, IndexTag::RPMTAG_PACKAGER),
+        (cli::DISTRIBUTION_ARG, IndexTag::RPMTAG_DISTRIBUTION),
+        (cli::BUG_URL_ARG, IndexTag::RPMTAG_BUGURL),
+    ] {
+        if let Some(value) = matches.value_of(arg) {
+            pkg.header.set(tag, Value::String(value.to_string()));
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum FileKind {
+    Regular,
+    Executable,
+    Config,
+    Doc,
+    /// A symlink pointing to the source path.
+    Symlink,
+    /// The spec file of a source rpm.
+    Spec,
+}
+
+/// rpm-rs has no constant for it, rpm finds the spec file of a source rpm by this flag.
+const RPMFILE_SPECFILE: u32 = 1 << 5;
+
+impl FileKind {
+    fn description(self) -> &'static str {
+        match self {
+            FileKind::Regular => "regular file",
+            FileKind::Executable => "executable file",
+            FileKind::Config => "config file",
+            FileKind::Doc => "doc file",
+            FileKind::Symlink => "symlink",
+            FileKind::Spec => "spec file",
+        }
+    }
+
+    /// The mode the file is added with, `None` keeps the one of the source file.
+    fn mode(self) -> Option<u32> {
+        match self {
+            FileKind::Executable => Some(0o100755),
+            _ => None,
+        }
+    }
+
+    fn flags(self) -> u32 {
+        match self {
+            FileKind::Config => rpm::RPMFILE_CONFIG as u32,
+            FileKind::Doc => rpm::RPMFILE_DOC as u32,
+            FileKind::Spec => RPMFILE_SPECFILE,
+            _ => 0,
+        }
+    }
+}
+
+/// A file that is going to be added to the package.
+struct FileEntry {
+    source: PathBuf,
+    dest: String,
+    kind: FileKind,
+}
+
+/// The destination of a file as it is sorted and stored, `./usr/bin/app` and `/usr/bin/app`
+/// are the same file.
+fn file_dest(dest: &str) -> String {
+    dest.strip_prefix('.').unwrap_or(dest).to_string()
+}
+
+/// Collect all files below `full_path`, walking directories in sorted order.
 fn add_dir<P: AsRef<Path>>(
     full_path: P,
-    target_path: &PathBuf,
-    mut builder: rpm::RPMBuilder,
-) -> Result<rpm::RPMBuilder, AppError> {
-    for entry in std::fs::read_dir(full_path)? {
-        let entry = entry?;
+    target_path: &Path,
+    files: &mut Vec<FileEntry>,
+) -> Result<(), AppError> {
+    let mut entries = std::fs::read_dir(full_path)?.collect::<Result<Vec<_>, _>>()?;
+    // read_dir order depends on the filesystem
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
         let metadata = entry.metadata()?;
-        let mut new_target = target_path.clone();
+        let mut new_target = target_path.to_path_buf();
 
         let source = if metadata.file_type().is_symlink() {
             std::fs::read_link(entry.path().as_path())?
@@ -237,23 +611,21 @@ fn add_dir<P: AsRef<Path>>(
             .ok_or_else(|| app_err!("path does not have filename"))?;
         new_target.push(file_name);
 
-        builder = if metadata.file_type().is_dir() {
-            add_dir(&source, &new_target, builder)?
+        if metadata.file_type().is_dir() {
+            add_dir(&source, &new_target, files)?;
         } else {
-            builder.with_file(
-                &source,
-                rpm::RPMFileOptions::new(new_target.to_string_lossy()),
-            )?
+            files.push(FileEntry {
+                source,
+                dest: file_dest(&new_target.to_string_lossy()),
+                kind: FileKind::Regular,
+            });
         }
     }
-    Ok(builder)
+    Ok(())
 }
 
-fn read_scriptlet(
-    scriptlet_type: &str,
-    matches: &clap::ArgMatches,
-) -> Result<Option<String>, AppError> {
-    if let Some(scriptlet_path) = matches.value_of(scriptlet_type) {
+fn read_scriptlet(scriptlet_type: &str, package: &PackageArgs) -> Result<Option<String>, AppError> {
+    if let Some(scriptlet_path) = package.value_of(scriptlet_type)? {
         let content = std::fs::read_to_string(scriptlet_path)
             .map_err(|e| app_err!("error reading {} {}: {}", scriptlet_type, scriptlet_path, e))?;
         return Ok(Some(content));
@@ -261,20 +633,18 @@ fn read_scriptlet(
     Ok(None)
 }
 
-fn parse_file_options(
-    raw_files: Vec<&str>,
-) -> Result<Vec<(&str, rpm::RPMFileOptionsBuilder)>, AppError> {
+fn parse_file_options(raw_files: Vec<&str>) -> Result<Vec<(&str, &str)>, AppError> {
     raw_files
         .iter()
         .map(|input| {
-            let parts: Vec<&str> = input.split(":").collect();
+            let parts: Vec<&str> = input.split(':').collect();
             if parts.len() != 2 {
                 return Err(app_err!(
                     "invalid file argument:{} it needs to be of the form <source-path>:<dest-path>",
                     input
                 ));
             }
-            Ok((parts[0], rpm::RPMFileOptions::new(parts[1])))
+            Ok((parts[0], parts[1]))
         })
         .collect()
 }
@@ -315,9 +685,9 @@ struct AppError {
 
 impl AppError {
     fn new<T: Into<String>>(cause: T) -> Self {
-        return AppError {
+        AppError {
             cause: cause.into(),
-        };
+        }
     }
 }
 
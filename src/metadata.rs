@@ -0,0 +1,369 @@
+//! A readable model of the main header of a package.
+
+use crate::header::Header;
+
+use rpm::IndexTag;
+use serde::Serialize;
+
+const RPMSENSE_LESS: u64 = 1 << 1;
+const RPMSENSE_GREATER: u64 = 1 << 2;
+const RPMSENSE_EQUAL: u64 = 1 << 3;
+
+const FILE_FLAGS: [(u64, &str); 8] = [
+    (1, "config"),
+    (1 << 1, "doc"),
+    (1 << 3, "missingok"),
+    (1 << 4, "noreplace"),
+    (1 << 5, "specfile"),
+    (1 << 6, "ghost"),
+    (1 << 7, "license"),
+    (1 << 8, "readme"),
+];
+
+/// Name, flags and version tags of every dependency kind.
+const DEPENDENCY_TAGS: [(&str, IndexTag, IndexTag, IndexTag); 8] = [
+    (
+        "requires",
+        IndexTag::RPMTAG_REQUIRENAME,
+        IndexTag::RPMTAG_REQUIREFLAGS,
+        IndexTag::RPMTAG_REQUIREVERSION,
+    ),
+    (
+        "provides",
+        IndexTag::RPMTAG_PROVIDENAME,
+        IndexTag::RPMTAG_PROVIDEFLAGS,
+        IndexTag::RPMTAG_PROVIDEVERSION,
+    ),
+    (
+        "conflicts",
+        IndexTag::RPMTAG_CONFLICTNAME,
+        IndexTag::RPMTAG_CONFLICTFLAGS,
+        IndexTag::RPMTAG_CONFLICTVERSION,
+    ),
+    (
+        "obsoletes",
+        IndexTag::RPMTAG_OBSOLETENAME,
+        IndexTag::RPMTAG_OBSOLETEFLAGS,
+        IndexTag::RPMTAG_OBSOLETEVERSION,
+    ),
+    (
+        "recommends",
+        IndexTag::RPMTAG_RECOMMENDNAME,
+        IndexTag::RPMTAG_RECOMMENDFLAGS,
+        IndexTag::RPMTAG_RECOMMENDVERSION,
+    ),
+    (
+        "suggests",
+        IndexTag::RPMTAG_SUGGESTNAME,
+        IndexTag::RPMTAG_SUGGESTFLAGS,
+        IndexTag::RPMTAG_SUGGESTVERSION,
+    ),
+    (
+        "supplements",
+        IndexTag::RPMTAG_SUPPLEMENTNAME,
+        IndexTag::RPMTAG_SUPPLEMENTFLAGS,
+        IndexTag::RPMTAG_SUPPLEMENTVERSION,
+    ),
+    (
+        "enhances",
+        IndexTag::RPMTAG_ENHANCENAME,
+        IndexTag::RPMTAG_ENHANCEFLAGS,
+        IndexTag::RPMTAG_ENHANCEVERSION,
+    ),
+];
+
+/// Script and interpreter tags of every scriptlet.
+const SCRIPTLET_TAGS: [(&str, IndexTag, IndexTag); 7] = [
+    (
+        "pretrans",
+        IndexTag::RPMTAG_PRETRANS,
+        IndexTag::RPMTAG_PRETRANSPROG,
+    ),
+    (
+        "preinstall",
+        IndexTag::RPMTAG_PREIN,
+        IndexTag::RPMTAG_PREINPROG,
+    ),
+    (
+        "postinstall",
+        IndexTag::RPMTAG_POSTIN,
+        IndexTag::RPMTAG_POSTINPROG,
+    ),
+    (
+        "preuninstall",
+        IndexTag::RPMTAG_PREUN,
+        IndexTag::RPMTAG_PREUNPROG,
+    ),
+    (
+        "postuninstall",
+        IndexTag::RPMTAG_POSTUN,
+        IndexTag::RPMTAG_POSTUNPROG,
+    ),
+    (
+        "posttrans",
+        IndexTag::RPMTAG_POSTTRANS,
+        IndexTag::RPMTAG_POSTTRANSPROG,
+    ),
+    (
+        "verify",
+        IndexTag::RPMTAG_VERIFYSCRIPT,
+        IndexTag::RPMTAG_VERIFYSCRIPTPROG,
+    ),
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PackageInfo {
+    pub name: String,
+    pub epoch: Option<u64>,
+    pub version: String,
+    pub release: String,
+    pub arch: String,
+    pub license: String,
+    pub summary: String,
+    pub description: String,
+    pub url: String,
+    pub vendor: String,
+    pub packager: String,
+    pub group: String,
+    pub distribution: String,
+    pub bug_url: String,
+    pub build_host: String,
+    pub build_time: u64,
+    pub source_rpm: String,
+    pub size: u64,
+    pub dependencies: Vec<Dependencies>,
+    pub scriptlets: Vec<Scriptlet>,
+    pub changelog: Vec<ChangelogEntry>,
+    pub files: Vec<FileInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Dependencies {
+    pub kind: &'static str,
+    pub entries: Vec<Dependency>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct Dependency {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub operator: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Scriptlet {
+    pub name: &'static str,
+    pub interpreter: Option<String>,
+    pub content: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChangelogEntry {
+    pub time: u64,
+    pub author: String,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FileInfo {
+    pub path: String,
+    pub mode: u16,
+    pub user: String,
+    pub group: String,
+    pub size: u64,
+    pub mtime: u64,
+    pub flags: Vec<&'static str>,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub digest: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub link_to: String,
+}
+
+impl PackageInfo {
+    pub fn from_header(header: &Header) -> Self {
+        let string = |tag| header.string(tag).unwrap_or_default().to_string();
+
+        let dependencies = DEPENDENCY_TAGS
+            .iter()
+            .map(|(kind, name_tag, flags_tag, version_tag)| Dependencies {
+                kind,
+                entries: dependencies(header, *name_tag, *flags_tag, *version_tag),
+            })
+            .filter(|d| !d.entries.is_empty())
+            .collect();
+
+        let scriptlets = SCRIPTLET_TAGS
+            .iter()
+            .filter_map(|(name, script_tag, prog_tag)| {
+                let content = header.string(*script_tag)?;
+                Some(Scriptlet {
+                    name,
+                    interpreter: header.string(*prog_tag).map(|s| s.to_string()),
+                    content: content.to_string(),
+                })
+            })
+            .collect();
+
+        let changelog = header
+            .ints(IndexTag::RPMTAG_CHANGELOGTIME)
+            .into_iter()
+            .zip(header.strings(IndexTag::RPMTAG_CHANGELOGNAME))
+            .zip(header.strings(IndexTag::RPMTAG_CHANGELOGTEXT))
+            .map(|((time, author), text)| ChangelogEntry {
+                time,
+                author: author.clone(),
+                text: text.clone(),
+            })
+            .collect();
+
+        PackageInfo {
+            name: string(IndexTag::RPMTAG_NAME),
+            epoch: header.int(IndexTag::RPMTAG_EPOCH),
+            version: string(IndexTag::RPMTAG_VERSION),
+            release: string(IndexTag::RPMTAG_RELEASE),
+            arch: string(IndexTag::RPMTAG_ARCH),
+            license: string(IndexTag::RPMTAG_LICENSE),
+            summary: string(IndexTag::RPMTAG_SUMMARY),
+            description: string(IndexTag::RPMTAG_DESCRIPTION),
+            url: string(IndexTag::RPMTAG_URL),
+            vendor: string(IndexTag::RPMTAG_VENDOR),
+            packager: string(IndexTag::RPMTAG_PACKAGER),
+            group: string(IndexTag::RPMTAG_GROUP),
+            distribution: string(IndexTag::RPMTAG_DISTRIBUTION),
+            bug_url: string(IndexTag::RPMTAG_BUGURL),
+            build_host: string(IndexTag::RPMTAG_BUILDHOST),
+            build_time: header.int(IndexTag::RPMTAG_BUILDTIME).unwrap_or_default(),
+            source_rpm: string(IndexTag::RPMTAG_SOURCERPM),
+            size: header
+                .int(IndexTag::RPMTAG_LONGSIZE)
+                .or_else(|| header.int(IndexTag::RPMTAG_SIZE))
+                .unwrap_or_default(),
+            dependencies,
+            scriptlets,
+            changelog,
+            files: files(header),
+        }
+    }
+}
+
+impl std::fmt::Display for Dependency {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match (&self.operator, &self.version) {
+            (Some(operator), Some(version)) => write!(f, "{} {} {}", self.name, operator, version),
+            _ => write!(f, "{}", self.name),
+        }
+    }
+}
+
+fn dependencies(
+    header: &Header,
+    name_tag: IndexTag,
+    flags_tag: IndexTag,
+    version_tag: IndexTag,
+) -> Vec<Dependency> {
+    let flags = header.ints(flags_tag);
+    let versions = header.strings(version_tag);
+    header
+        .strings(name_tag)
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let flags = flags.get(i).copied().unwrap_or_default();
+            let operator = match flags & (RPMSENSE_LESS | RPMSENSE_GREATER | RPMSENSE_EQUAL) {
+                RPMSENSE_LESS => Some("<"),
+                f if f == RPMSENSE_LESS | RPMSENSE_EQUAL => Some("<="),
+                RPMSENSE_EQUAL => Some("="),
+                f if f == RPMSENSE_GREATER | RPMSENSE_EQUAL => Some(">="),
+                RPMSENSE_GREATER => Some(">"),
+                _ => None,
+            };
+            let version = versions.get(i).filter(|v| !v.is_empty()).cloned();
+            Dependency {
+                name: name.clone(),
+                operator: operator.filter(|_| version.is_some()),
+                version,
+            }
+        })
+        .collect()
+}
+
+fn files(header: &Header) -> Vec<FileInfo> {
+    let dir_names = header.strings(IndexTag::RPMTAG_DIRNAMES);
+    let paths: Vec<String> = if dir_names.is_empty() {
+        header.strings(IndexTag::RPMTAG_OLDFILENAMES).to_vec()
+    } else {
+        header
+            .strings(IndexTag::RPMTAG_BASENAMES)
+            .iter()
+            .zip(header.ints(IndexTag::RPMTAG_DIRINDEXES))
+            .map(|(base_name, index)| {
+                let dir = dir_names.get(index as usize).map(|d| d.as_str());
+                format!("{}{}", dir.unwrap_or_default(), base_name)
+            })
+            .collect()
+    };
+
+    let modes = header.ints(IndexTag::RPMTAG_FILEMODES);
+    let users = header.strings(IndexTag::RPMTAG_FILEUSERNAME);
+    let groups = header.strings(IndexTag::RPMTAG_FILEGROUPNAME);
+    let mut sizes = header.ints(IndexTag::RPMTAG_LONGFILESIZES);
+    if sizes.is_empty() {
+        sizes = header.ints(IndexTag::RPMTAG_FILESIZES);
+    }
+    let mtimes = header.ints(IndexTag::RPMTAG_FILEMTIMES);
+    let flags = header.ints(IndexTag::RPMTAG_FILEFLAGS);
+    let digests = header.strings(IndexTag::RPMTAG_FILEDIGESTS);
+    let link_tos = header.strings(IndexTag::RPMTAG_FILELINKTOS);
+
+    paths
+        .into_iter()
+        .enumerate()
+        .map(|(i, path)| {
+            let file_flags = flags.get(i).copied().unwrap_or_default();
+            FileInfo {
+                path,
+                mode: modes.get(i).copied().unwrap_or_default() as u16,
+                user: users.get(i).cloned().unwrap_or_default(),
+                group: groups.get(i).cloned().unwrap_or_default(),
+                size: sizes.get(i).copied().unwrap_or_default(),
+                mtime: mtimes.get(i).copied().unwrap_or_default(),
+                flags: FILE_FLAGS
+                    .iter()
+                    .filter(|(bit, _)| file_flags & bit != 0)
+                    .map(|(_, name)| *name)
+                    .collect(),
+                digest: digests.get(i).cloned().unwrap_or_default(),
+                link_to: link_tos.get(i).cloned().unwrap_or_default(),
+            }
+        })
+        .collect()
+}
+
+/// Render a file mode the way `ls -l` does.
+pub fn format_mode(mode: u16) -> String {
+    let file_type = match mode & 0o170000 {
+        0o040000 => 'd',
+        0o120000 => 'l',
+        0o020000 => 'c',
+        0o060000 => 'b',
+        0o010000 => 'p',
+        0o140000 => 's',
+        _ => '-',
+    };
+    let mut out = String::with_capacity(10);
+    out.push(file_type);
+    for (shift, special, special_char) in [(6, 0o4000, 's'), (3, 0o2000, 's'), (0, 0o1000, 't')] {
+        let bits = (mode >> shift) & 0o7;
+        out.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        out.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        out.push(match (bits & 0o1 != 0, mode & special != 0) {
+            (true, true) => special_char,
+            (false, true) => special_char.to_ascii_uppercase(),
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
+    }
+    out
+}
@@ -0,0 +1,69 @@
+//! Naming of the built packages.
+
+use crate::cli;
+use crate::AppError;
+
+use std::path::PathBuf;
+
+/// The file name of a package if no `--out` is given, as repositories name them.
+pub const DEFAULT_TEMPLATE: &str = "{name}-{version}-{release}.{arch}.rpm";
+
+/// The path the package `name` for `arch` is written to, from `--out` and `--out-dir`.
+pub fn output_path(
+    matches: &clap::ArgMatches,
+    name: &str,
+    arch: &str,
+) -> Result<PathBuf, AppError> {
+    let template = matches.value_of(cli::OUT_ARG).unwrap_or(DEFAULT_TEMPLATE);
+    let file = expand(
+        template,
+        &[
+            ("name", name),
+            ("epoch", matches.value_of(cli::EPOCH_ARG).unwrap()),
+            ("version", matches.value_of(cli::VERSION_ARG).unwrap()),
+            ("release", matches.value_of(cli::RELEASE_ARG).unwrap()),
+            ("arch", arch),
+        ],
+    )?;
+    Ok(match matches.value_of(cli::OUT_DIR_ARG) {
+        Some(dir) => PathBuf::from(dir).join(file),
+        None => PathBuf::from(file),
+    })
+}
+
+/// The path the source rpm of the main package is written to.
+pub fn source_rpm_path(matches: &clap::ArgMatches) -> Result<PathBuf, AppError> {
+    output_path(matches, matches.value_of(cli::NAME_ARG).unwrap(), "src")
+}
+
+/// Replace every `{field}` in `template` with its value.
+fn expand(template: &str, fields: &[(&str, &str)]) -> Result<String, AppError> {
+    let mut expanded = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        expanded.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| app_err!("unterminated placeholder in output template {}", template))?;
+        let field = &rest[start + 1..start + end];
+        let (_, value) = fields
+            .iter()
+            .find(|(name, _)| *name == field)
+            .ok_or_else(|| {
+                app_err!(
+                    "unknown placeholder {{{}}} in output template {}, expected one of {}",
+                    field,
+                    template,
+                    fields
+                        .iter()
+                        .map(|(name, _)| format!("{{{}}}", name))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            })?;
+        expanded.push_str(value);
+        rest = &rest[start + end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
@@ -0,0 +1,368 @@
+//! Reading and writing whole rpm files.
+//!
+//! `rpm::RPMPackage::parse` is not used here, for three reasons:
+//!
+//! - it fails with `InvalidTag` on any tag rpm-rs has no enum variant for, e.g.
+//!   `RPMSIGTAG_LONGSIZE` or `RPMTAG_PAYLOADDIGESTALT` written by current rpm versions,
+//!   so packages built by rpmbuild could not be verified, queried or signed,
+//! - its headers can neither be changed nor serialized again, which signing needs,
+//! - it keeps the complete payload in memory.
+//!
+//! Packages are therefore split into lead, headers and payload here, the headers are
+//! parsed by [`crate::header`].
+
+use crate::digest;
+use crate::header::{Header, Value};
+use crate::signing::Signer;
+use crate::AppError;
+
+use rpm::signature::Signing;
+use rpm::{IndexSignatureTag, IndexTag};
+use sha2::Digest;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const LEAD_SIZE: usize = 96;
+const RPM_MAGIC: [u8; 4] = [0xed, 0xab, 0xee, 0xdb];
+
+/// Size of header and payload if it exceeds 4 GiB, rpm-rs does not know this tag.
+pub const RPMSIGTAG_LONGSIZE: u32 = 270;
+
+/// Size of the uncompressed payload if it exceeds 4 GiB, rpm-rs does not know this tag either.
+pub const RPMSIGTAG_LONGARCHIVESIZE: u32 = 271;
+
+/// An rpm file split into its raw parts.
+///
+/// The main header is kept as it was read, since the digests and signatures span its bytes.
+pub struct Package {
+    pub lead: Vec<u8>,
+    pub signature: Header,
+    pub header: Header,
+    pub header_bytes: Vec<u8>,
+    pub payload: Payload,
+}
+
+/// The compressed payload of a package.
+///
+/// Packages that are built keep it in a temporary file and opened packages read it from
+/// the rpm file, so their size is not limited by memory.
+pub enum Payload {
+    Memory(Vec<u8>),
+    /// The payload starts at the given offset and reaches up to the end of the file.
+    File(std::fs::File, u64),
+}
+
+impl Payload {
+    pub fn size(&self) -> io::Result<u64> {
+        match self {
+            Payload::Memory(data) => Ok(data.len() as u64),
+            Payload::File(file, offset) => Ok(file.metadata()?.len().saturating_sub(*offset)),
+        }
+    }
+
+    /// Read the payload from the start.
+    pub fn reader(&self) -> io::Result<Box<dyn Read + '_>> {
+        match self {
+            Payload::Memory(data) => Ok(Box::new(data.as_slice())),
+            Payload::File(file, offset) => {
+                let mut file = file;
+                file.seek(SeekFrom::Start(*offset))?;
+                Ok(Box::new(io::BufReader::new(file)))
+            }
+        }
+    }
+}
+
+impl Package {
+    /// Read lead and headers of the package at `path`, the payload stays in the file.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, AppError> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path)
+            .map_err(|e| app_err!("unable to read package {}: {}", path.display(), e))?;
+        Self::read(file).map_err(|e| app_err!("invalid package {}: {}", path.display(), e))
+    }
+
+    fn read(file: std::fs::File) -> Result<Self, AppError> {
+        let mut reader = io::BufReader::new(file);
+        let mut lead = Vec::new();
+        (&mut reader)
+            .take(LEAD_SIZE as u64)
+            .read_to_end(&mut lead)?;
+        if lead.len() < LEAD_SIZE || lead[..4] != RPM_MAGIC {
+            return Err(app_err!("not an rpm file"));
+        }
+        let signature_bytes = read_header(&mut reader)?;
+        let (signature, signature_len) = Header::parse(&signature_bytes)?;
+        // the signature header is padded to a multiple of 8 bytes
+        let padding = (8 - signature_len % 8) % 8;
+        io::copy(&mut (&mut reader).take(padding as u64), &mut io::sink())?;
+        let header_bytes = read_header(&mut reader)?;
+        let (header, _) = Header::parse(&header_bytes)?;
+        let offset = (LEAD_SIZE + signature_len + padding + header_bytes.len()) as u64;
+        Ok(Package {
+            lead,
+            signature,
+            header,
+            header_bytes,
+            payload: Payload::File(reader.into_inner(), offset),
+        })
+    }
+
+    /// Convert a package built by rpm-rs, so its headers can be changed.
+    pub fn from_rpm(pkg: &rpm::RPMPackage) -> Result<Self, AppError> {
+        let mut raw = Vec::new();
+        pkg.write(&mut raw)?;
+        Self::parse(raw)
+    }
+
+    pub fn parse(mut raw: Vec<u8>) -> Result<Self, AppError> {
+        let (signature, header_start) = parse_signature(&raw)?;
+        let (header, header_len) = Header::parse(&raw[header_start..])?;
+        let payload = raw.split_off(header_start + header_len);
+        let header_bytes = raw.split_off(header_start);
+        raw.truncate(LEAD_SIZE);
+        Ok(Package {
+            lead: raw,
+            signature,
+            header,
+            header_bytes,
+            payload: Payload::Memory(payload),
+        })
+    }
+
+    pub fn write<W: Write>(&self, out: &mut W) -> Result<(), AppError> {
+        let signature = self.signature.to_bytes(true);
+        out.write_all(&self.lead)?;
+        out.write_all(&signature)?;
+        out.write_all(&vec![0; (8 - signature.len() % 8) % 8])?;
+        out.write_all(&self.header_bytes)?;
+        io::copy(&mut self.payload.reader()?, out)?;
+        Ok(())
+    }
+
+    /// Write the package to `path` without ever leaving a partially written file behind.
+    pub fn write_atomic<P: AsRef<Path>>(&self, path: P) -> Result<(), AppError> {
+        let path = path.as_ref();
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| app_err!("invalid package path {}", path.display()))?;
+        let tmp_path = path.with_file_name(format!(
+            ".{}.{}.tmp",
+            file_name.to_string_lossy(),
+            std::process::id()
+        ));
+        let result = (|| {
+            let mut out = std::fs::File::create(&tmp_path)?;
+            self.write(&mut out)?;
+            if let Ok(metadata) = std::fs::metadata(path) {
+                out.set_permissions(metadata.permissions())?;
+            }
+            out.sync_all()?;
+            std::fs::rename(&tmp_path, path)?;
+            Ok(())
+        })();
+        if result.is_err() {
+            let _ = std::fs::remove_file(&tmp_path);
+        }
+        result.map_err(|e: AppError| app_err!("unable to write package {}: {}", path.display(), e))
+    }
+
+    /// Serialize the main header again after its entries or the payload changed.
+    pub fn update_header(&mut self) -> Result<(), AppError> {
+        if self.header.get(IndexTag::RPMTAG_PAYLOADDIGEST).is_some() {
+            let algorithm = self
+                .header
+                .int(IndexTag::RPMTAG_PAYLOADDIGESTALGO)
+                .unwrap_or(rpm::PGPHASHALGO_SHA256 as u64);
+            let digest = digest::hex_digest(algorithm, self.payload.reader()?)?
+                .ok_or_else(|| app_err!("unsupported payload digest algorithm {}", algorithm))?;
+            self.header.set(
+                IndexTag::RPMTAG_PAYLOADDIGEST,
+                Value::StringArray(vec![digest]),
+            );
+        }
+        self.header_bytes = self.header.to_bytes(false);
+        Ok(())
+    }
+
+    /// Replace the digests and signatures in the signature header with fresh digests and, if
+    /// given, pgp signatures.
+    ///
+    /// Everything else in the signature header, e.g. the payload size or file signatures, is kept.
+    pub fn sign(&mut self, signer: Option<&Signer>) -> Result<(), AppError> {
+        let mut signature = self.signature.clone();
+        for tag in [
+            IndexSignatureTag::RPMSIGTAG_DSA,
+            IndexSignatureTag::RPMSIGTAG_RSA,
+            IndexSignatureTag::RPMSIGTAG_PGP,
+            IndexSignatureTag::RPMSIGTAG_GPG,
+        ] {
+            signature.remove(tag);
+        }
+        let size = self.header_bytes.len() as u64 + self.payload.size()?;
+        set_size(&mut signature, size);
+
+        let mut md5 = md5::Md5::new();
+        md5.update(&self.header_bytes);
+        io::copy(&mut self.payload.reader()?, &mut md5)?;
+        signature.set(
+            IndexSignatureTag::RPMSIGTAG_MD5,
+            Value::Bin(md5.finalize().to_vec()),
+        );
+        signature.set(
+            IndexSignatureTag::RPMSIGTAG_SHA1,
+            Value::String(hex::encode(sha1::Sha1::digest(&self.header_bytes))),
+        );
+        signature.set(
+            IndexSignatureTag::RPMSIGTAG_SHA256,
+            Value::String(hex::encode(sha2::Sha256::digest(&self.header_bytes))),
+        );
+
+        if let Some(signer) = signer {
+            let header_only = signer.sign(self.header_bytes.as_slice())?;
+            let header_and_payload =
+                signer.sign(self.header_bytes.as_slice().chain(self.payload.reader()?))?;
+            signature.set(IndexSignatureTag::RPMSIGTAG_RSA, Value::Bin(header_only));
+            signature.set(
+                IndexSignatureTag::RPMSIGTAG_PGP,
+                Value::Bin(header_and_payload),
+            );
+        }
+
+        self.signature = signature;
+        Ok(())
+    }
+}
+
+/// Record the size of header and payload, in the 64 bit tag only if it needs it.
+fn set_size(signature: &mut Header, size: u64) {
+    if size > u32::MAX as u64 {
+        signature.remove(IndexSignatureTag::RPMSIGTAG_SIZE);
+        signature.set(RPMSIGTAG_LONGSIZE, Value::Int64(vec![size]));
+    } else {
+        signature.remove(RPMSIGTAG_LONGSIZE);
+        signature.set(
+            IndexSignatureTag::RPMSIGTAG_SIZE,
+            Value::Int32(vec![size as u32]),
+        );
+    }
+}
+
+/// Record the size of the uncompressed payload, in the 64 bit tag only if it needs it.
+pub fn set_archive_size(signature: &mut Header, size: u64) {
+    if size > u32::MAX as u64 {
+        signature.remove(IndexSignatureTag::RPMSIGTAG_PAYLOADSIZE);
+        signature.set(RPMSIGTAG_LONGARCHIVESIZE, Value::Int64(vec![size]));
+    } else {
+        signature.remove(RPMSIGTAG_LONGARCHIVESIZE);
+        signature.set(
+            IndexSignatureTag::RPMSIGTAG_PAYLOADSIZE,
+            Value::Int32(vec![size as u32]),
+        );
+    }
+}
+
+/// Start and end offset of the main header within the file `pkg` was opened from.
+pub fn header_range(pkg: &Package) -> Result<(u64, u64), AppError> {
+    match pkg.payload {
+        // the payload of a package that is built starts its temporary file
+        Payload::File(_, header_end) if header_end > 0 => {
+            Ok((header_end - pkg.header_bytes.len() as u64, header_end))
+        }
+        _ => Err(app_err!("the package was not opened from a file")),
+    }
+}
+
+/// Read the raw bytes of the header starting at the current position of `reader`.
+fn read_header<R: Read>(reader: &mut R) -> Result<Vec<u8>, AppError> {
+    let mut bytes = Vec::new();
+    reader.take(16).read_to_end(&mut bytes)?;
+    if bytes.len() < 16 {
+        return Err(app_err!("header is truncated"));
+    }
+    let num_entries = u32::from_be_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as u64;
+    let data_size = u32::from_be_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]) as u64;
+    let len = 16 * num_entries + data_size;
+    reader.take(len).read_to_end(&mut bytes)?;
+    if (bytes.len() as u64) < 16 + len {
+        return Err(app_err!("header is truncated"));
+    }
+    Ok(bytes)
+}
+
+/// Parse the signature header, returning it and the offset of the main header.
+fn parse_signature(raw: &[u8]) -> Result<(Header, usize), AppError> {
+    if raw.len() < LEAD_SIZE || raw[..4] != RPM_MAGIC {
+        return Err(app_err!("not an rpm file"));
+    }
+    let (signature, signature_len) = Header::parse(&raw[LEAD_SIZE..])?;
+    // the signature header is padded to a multiple of 8 bytes
+    let header_start = LEAD_SIZE + signature_len + (8 - signature_len % 8) % 8;
+    if header_start > raw.len() {
+        return Err(app_err!("header is truncated"));
+    }
+    Ok((signature, header_start))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn size_over_4_gib() {
+        let mut signature = Header::default();
+        set_size(&mut signature, 5 << 30);
+        assert_eq!(signature.int(RPMSIGTAG_LONGSIZE), Some(5 << 30));
+        assert!(signature.get(IndexSignatureTag::RPMSIGTAG_SIZE).is_none());
+
+        let mut signature = Header::default();
+        set_size(&mut signature, 1024);
+        assert_eq!(signature.int(IndexSignatureTag::RPMSIGTAG_SIZE), Some(1024));
+        assert!(signature.get(RPMSIGTAG_LONGSIZE).is_none());
+
+        let mut signature = Header::default();
+        set_archive_size(&mut signature, 5 << 30);
+        assert_eq!(signature.int(RPMSIGTAG_LONGARCHIVESIZE), Some(5 << 30));
+        assert!(signature
+            .get(IndexSignatureTag::RPMSIGTAG_PAYLOADSIZE)
+            .is_none());
+    }
+
+    #[test]
+    fn sign_keeps_other_signature_entries() {
+        let header = Header::default();
+        let mut signature = Header::default();
+        signature.set(
+            IndexSignatureTag::RPMSIGTAG_PAYLOADSIZE,
+            Value::Int32(vec![1024]),
+        );
+        signature.set(
+            IndexSignatureTag::RPMSIGTAG_FILESIGNATURES,
+            Value::StringArray(vec!["0300".to_string()]),
+        );
+        signature.set(IndexSignatureTag::RPMSIGTAG_DSA, Value::Bin(vec![1, 2, 3]));
+        signature.set(RPMSIGTAG_LONGSIZE, Value::Int64(vec![5 << 30]));
+        let mut pkg = Package {
+            lead: vec![0; LEAD_SIZE],
+            signature,
+            header_bytes: header.to_bytes(false),
+            header,
+            payload: Payload::Memory(b"payload".to_vec()),
+        };
+        pkg.sign(None).unwrap();
+
+        let signature = &pkg.signature;
+        assert_eq!(
+            signature.int(IndexSignatureTag::RPMSIGTAG_PAYLOADSIZE),
+            Some(1024)
+        );
+        assert_eq!(
+            signature.strings(IndexSignatureTag::RPMSIGTAG_FILESIGNATURES),
+            ["0300"]
+        );
+        assert!(signature.get(IndexSignatureTag::RPMSIGTAG_DSA).is_none());
+        assert!(signature.get(RPMSIGTAG_LONGSIZE).is_none());
+        assert!(signature.get(IndexSignatureTag::RPMSIGTAG_SIZE).is_some());
+        assert!(signature.get(IndexSignatureTag::RPMSIGTAG_SHA256).is_some());
+    }
+}
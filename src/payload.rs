@@ -0,0 +1,678 @@
+//! Reading and writing the cpio archive inside the payload of a package.
+
+use crate::compression::{Compression, Encoder};
+use crate::digest::{self, DigestWriter};
+use crate::header::{Header, Value};
+use crate::metadata::FileInfo;
+use crate::AppError;
+
+use rpm::IndexTag;
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+const XZ_MAGIC: [u8; 6] = [0xfd, b'7', b'z', b'X', b'Z', 0x00];
+const BZIP2_MAGIC: &[u8] = b"BZh";
+// legacy lzma streams have no magic, this is the properties byte rpm and xz write
+const LZMA_MAGIC: [u8; 3] = [0x5d, 0x00, 0x00];
+const CPIO_NEWC_MAGIC: &[u8] = b"070701";
+// rpm writes files larger than 4 GiB with this header, it only holds the index of the file
+const CPIO_STRIPPED_MAGIC: &[u8] = b"07070X";
+const CPIO_HEADER_SIZE: u64 = 110;
+const CPIO_STRIPPED_HEADER_SIZE: u64 = 14;
+const CPIO_TRAILER: &str = "TRAILER!!!";
+
+/// Wrap the payload in a reader that yields the uncompressed cpio archive.
+///
+/// The compression is detected from the data itself, packages built without
+/// compression do not always record it in their header.
+pub fn decoder<'a, R: Read + 'a>(payload: R) -> Result<Box<dyn Read + 'a>, AppError> {
+    let mut payload = BufReader::new(payload);
+    let magic: Vec<u8> = payload.fill_buf()?.iter().take(6).copied().collect();
+    if magic.starts_with(&GZIP_MAGIC) {
+        Ok(Box::new(flate2::bufread::GzDecoder::new(payload)))
+    } else if magic.starts_with(&ZSTD_MAGIC) {
+        // the zstd decoder fails on empty reads, which cpio does for empty files
+        let decoder = zstd::stream::read::Decoder::with_buffer(payload)?;
+        Ok(Box::new(BufReader::new(decoder)))
+    } else if magic.starts_with(&XZ_MAGIC) {
+        Ok(Box::new(xz2::bufread::XzDecoder::new(payload)))
+    } else if magic.starts_with(&LZMA_MAGIC) {
+        let stream = xz2::stream::Stream::new_lzma_decoder(u64::MAX)
+            .map_err(|e| app_err!("unable to set up lzma decompression: {}", e))?;
+        Ok(Box::new(xz2::bufread::XzDecoder::new_stream(
+            payload, stream,
+        )))
+    } else if magic.starts_with(BZIP2_MAGIC) {
+        Ok(Box::new(bzip2::bufread::BzDecoder::new(payload)))
+    } else if magic.starts_with(CPIO_NEWC_MAGIC) || magic.starts_with(CPIO_STRIPPED_MAGIC) {
+        Ok(Box::new(payload))
+    } else {
+        Err(app_err!("unsupported payload compression"))
+    }
+}
+
+/// A single entry of the cpio archive.
+pub struct ArchiveEntry {
+    pub path: String,
+    pub mode: u32,
+    pub mtime: u32,
+    pub size: u64,
+}
+
+/// Call `f` for every entry of the uncompressed cpio archive, in archive order, together
+/// with a reader for its content.
+///
+/// Entries of files larger than 4 GiB only hold the index of the file in the header, their
+/// path, mode and size are taken from `files`.
+pub fn for_each_entry<R: Read, F>(
+    mut archive: R,
+    files: &[FileInfo],
+    mut f: F,
+) -> Result<(), AppError>
+where
+    F: FnMut(ArchiveEntry, &mut dyn Read) -> Result<(), AppError>,
+{
+    loop {
+        let mut magic = [0; 6];
+        archive.read_exact(&mut magic)?;
+        let entry = if magic == CPIO_NEWC_MAGIC {
+            let mut fields = [0; 13 * 8];
+            archive.read_exact(&mut fields)?;
+            let field = |i: usize| parse_hex(&fields[i * 8..(i + 1) * 8]);
+            let mut name = vec![0; field(11)? as usize];
+            archive.read_exact(&mut name)?;
+            skip(&mut archive, padding(CPIO_HEADER_SIZE + name.len() as u64))?;
+            let path = String::from_utf8_lossy(&name)
+                .trim_end_matches('\0')
+                .to_string();
+            if path == CPIO_TRAILER {
+                return Ok(());
+            }
+            ArchiveEntry {
+                path,
+                mode: field(1)?,
+                mtime: field(5)?,
+                size: field(6)? as u64,
+            }
+        } else if magic == CPIO_STRIPPED_MAGIC {
+            let mut index = [0; 8];
+            archive.read_exact(&mut index)?;
+            skip(&mut archive, padding(CPIO_STRIPPED_HEADER_SIZE))?;
+            let index = parse_hex(&index)? as usize;
+            let file = files
+                .get(index)
+                .ok_or_else(|| app_err!("invalid file index {} in payload", index))?;
+            ArchiveEntry {
+                path: format!(".{}", file.path),
+                mode: file.mode as u32,
+                mtime: file.mtime as u32,
+                size: file.size,
+            }
+        } else {
+            return Err(app_err!("invalid cpio archive in payload"));
+        };
+        let size = entry.size;
+        let mut content = (&mut archive).take(size);
+        f(entry, &mut content)?;
+        // skip whatever the callback did not read
+        io::copy(&mut content, &mut io::sink())?;
+        if content.limit() > 0 {
+            return Err(app_err!("payload is truncated"));
+        }
+        skip(&mut archive, padding(size))?;
+    }
+}
+
+/// Number of bytes needed to pad `len` to the 4 byte alignment of cpio.
+fn padding(len: u64) -> u64 {
+    (4 - len % 4) % 4
+}
+
+fn skip<R: Read>(reader: &mut R, len: u64) -> io::Result<()> {
+    io::copy(&mut reader.take(len), &mut io::sink()).map(|_| ())
+}
+
+fn parse_hex(raw: &[u8]) -> Result<u32, AppError> {
+    std::str::from_utf8(raw)
+        .ok()
+        .and_then(|raw| u32::from_str_radix(raw, 16).ok())
+        .ok_or_else(|| app_err!("invalid cpio header in payload"))
+}
+
+/// What the header records about a file in the payload.
+struct FileRecord {
+    path: String,
+    size: u64,
+    mode: u32,
+    mtime: u32,
+    digest: String,
+    flags: u32,
+    /// The target of a symlink, empty for regular files.
+    link_to: String,
+}
+
+/// Streams files into a compressed cpio archive.
+///
+/// Files are read in chunks and never held in memory as a whole, the header entries
+/// describing them are only added once the archive is complete.
+pub struct PayloadWriter<W: Write> {
+    archive: CountingWriter<Encoder<DigestWriter<W>>>,
+    compression: Compression,
+    file_digest: u64,
+    files: Vec<FileRecord>,
+    relative: bool,
+    large_files: bool,
+}
+
+impl<W: Write> PayloadWriter<W> {
+    /// Create a writer recording file digests with `file_digest`, one of the `PGPHASHALGO_*`
+    /// algorithms.
+    pub fn new(writer: W, compression: Compression, file_digest: u64) -> Result<Self, AppError> {
+        if digest::hasher(file_digest).is_none() {
+            return Err(app_err!(
+                "unsupported file digest algorithm {}",
+                file_digest
+            ));
+        }
+        let writer = DigestWriter::new(writer, sha256());
+        Ok(PayloadWriter {
+            archive: CountingWriter {
+                inner: compression.encoder(writer)?,
+                count: 0,
+            },
+            compression,
+            file_digest,
+            files: Vec::new(),
+            relative: false,
+            large_files: false,
+        })
+    }
+
+    /// Store files by their plain name instead of an absolute path, as source packages do.
+    pub fn relative_paths(mut self) -> Self {
+        self.relative = true;
+        self
+    }
+
+    /// Write every entry with the stripped header rpm uses once a file is larger than 4 GiB.
+    ///
+    /// rpm reads either all entries of an archive with stripped headers or none, so this has to
+    /// be decided before the first file is added.
+    pub fn large_files(mut self) -> Self {
+        self.large_files = true;
+        self
+    }
+
+    /// Append the regular file `source` as `dest`.
+    ///
+    /// rpm expects the archive in the order of the file list, so files have to be
+    /// added sorted by their destination. Without a `mode` the one of `source` is used.
+    pub fn add_file(
+        &mut self,
+        source: &Path,
+        dest: &str,
+        mode: Option<u32>,
+        flags: u32,
+    ) -> Result<(), AppError> {
+        let path = self.check_path(dest)?;
+        let file = File::open(source)?;
+        let metadata = file.metadata()?;
+        let mode = mode.unwrap_or_else(|| metadata.permissions().mode());
+        let mtime = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as u32)
+            .unwrap_or_default();
+        let size = metadata.len();
+
+        let hasher = digest::hasher(self.file_digest).unwrap();
+        let mut content = DigestWriter::new(self.entry(path, mode, size)?, hasher);
+        // a file that grows while it is read is cut off
+        let copied = io::copy(&mut file.take(size), &mut content)?;
+        if copied != size {
+            return Err(app_err!("{} changed while it was read", source.display()));
+        }
+        let (content, digest) = content.finish();
+        content.finish(size)?;
+
+        self.files.push(FileRecord {
+            path: path.to_string(),
+            size,
+            mode,
+            mtime,
+            digest: hex::encode(digest),
+            flags,
+            link_to: String::new(),
+        });
+        Ok(())
+    }
+
+    /// Append a symlink `dest` pointing to `target`.
+    ///
+    /// Like files, symlinks have to be added sorted by their destination.
+    pub fn add_symlink(&mut self, dest: &str, target: &str) -> Result<(), AppError> {
+        let path = self.check_path(dest)?.to_string();
+        let mode = 0o120777;
+        let mtime = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as u32)
+            .unwrap_or_default();
+        let mut entry = self.entry(&path, mode, target.len() as u64)?;
+        entry.write_all(target.as_bytes())?;
+        entry.finish(target.len() as u64)?;
+
+        self.files.push(FileRecord {
+            path,
+            size: target.len() as u64,
+            mode,
+            mtime,
+            // rpm records no digest for symlinks
+            digest: String::new(),
+            flags: 0,
+            link_to: target.to_string(),
+        });
+        Ok(())
+    }
+
+    /// Write the header of the next entry, its content follows through the returned writer.
+    fn entry(
+        &mut self,
+        path: &str,
+        mode: u32,
+        size: u64,
+    ) -> Result<ArchiveContent<'_, CountingWriter<Encoder<DigestWriter<W>>>>, AppError> {
+        let index = self.files.len() as u32;
+        if self.large_files {
+            // newc can not represent the size, rpm takes everything but the index from the header
+            let mut entry = Vec::with_capacity(16);
+            entry.extend_from_slice(CPIO_STRIPPED_MAGIC);
+            entry.extend_from_slice(format!("{:08x}", index).as_bytes());
+            entry.resize(entry.len() + padding(CPIO_STRIPPED_HEADER_SIZE) as usize, 0);
+            self.archive.write_all(&entry)?;
+            Ok(ArchiveContent::Stripped(&mut self.archive))
+        } else if size > u32::MAX as u64 {
+            Err(app_err!(
+                "{} is larger than 4 GiB, but the payload is not written for large files",
+                path
+            ))
+        } else {
+            let name = self.archive_name(path);
+            let entry = cpio::newc::Builder::new(&name)
+                .mode(mode)
+                .ino(index + 1)
+                .write(&mut self.archive, size as u32);
+            Ok(ArchiveContent::Newc(entry))
+        }
+    }
+
+    /// The path of `dest` as it is recorded, it has to sort after all files added so far.
+    fn check_path<'a>(&self, dest: &'a str) -> Result<&'a str, AppError> {
+        let path = if self.relative {
+            if dest.is_empty() || dest.contains('/') {
+                return Err(app_err!(
+                    "invalid source file name {}, expected a name without directories",
+                    dest
+                ));
+            }
+            dest
+        } else {
+            let path = dest.strip_prefix('.').unwrap_or(dest);
+            if !path.starts_with('/') || path.ends_with('/') {
+                return Err(app_err!(
+                    "invalid destination path {}, expected an absolute path to a file",
+                    dest
+                ));
+            }
+            path
+        };
+        if let Some(last) = self.files.last() {
+            if last.path.as_str() >= path {
+                return Err(app_err!(
+                    "files have to be added in sorted order, {} follows {}",
+                    path,
+                    last.path
+                ));
+            }
+        }
+        Ok(path)
+    }
+
+    /// The name of `path` in the cpio archive.
+    fn archive_name(&self, path: &str) -> String {
+        if self.relative {
+            path.to_string()
+        } else {
+            format!(".{}", path)
+        }
+    }
+
+    /// Complete the archive and add the file list, size and payload tags to `header`.
+    ///
+    /// Returns the writer together with the size of the uncompressed archive, which belongs
+    /// into the signature header.
+    pub fn finish(self, header: &mut Header) -> Result<(W, u64), AppError> {
+        let archive = cpio::newc::trailer(self.archive)?;
+        let archive_size = archive.count;
+        let (writer, payload_digest) = archive.inner.finish()?.finish();
+
+        let total_size: u64 = self.files.iter().map(|f| f.size).sum();
+        if total_size > u32::MAX as u64 {
+            header.remove(IndexTag::RPMTAG_SIZE);
+            header.set(IndexTag::RPMTAG_LONGSIZE, Value::Int64(vec![total_size]));
+        } else {
+            header.set(IndexTag::RPMTAG_SIZE, Value::Int32(vec![total_size as u32]));
+        }
+        if !self.files.is_empty() {
+            set_file_tags(header, &self.files, self.file_digest, self.large_files);
+        }
+        if self.large_files {
+            add_rpmlib_requirement(header, "rpmlib(LargeFiles)", "4.12.0-1");
+        }
+        header.set(
+            IndexTag::RPMTAG_PAYLOADDIGEST,
+            Value::StringArray(vec![hex::encode(payload_digest)]),
+        );
+        header.set(
+            IndexTag::RPMTAG_PAYLOADDIGESTALGO,
+            Value::Int32(vec![rpm::PGPHASHALGO_SHA256 as u32]),
+        );
+        self.compression.record(header);
+        Ok((writer, archive_size))
+    }
+}
+
+/// Counts the bytes written through it.
+struct CountingWriter<W: Write> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn sha256() -> Box<dyn sha2::digest::DynDigest> {
+    digest::hasher(rpm::PGPHASHALGO_SHA256 as u64).unwrap()
+}
+
+/// Add the file list in the compressed form rpm uses, with separate directory and base names.
+///
+/// rpm expects stripped entries in the archive if the sizes are recorded as `LONGFILESIZES`.
+fn set_file_tags(
+    header: &mut Header,
+    files: &[FileRecord],
+    digest_algorithm: u64,
+    large_files: bool,
+) {
+    if large_files {
+        header.set(
+            IndexTag::RPMTAG_LONGFILESIZES,
+            Value::Int64(files.iter().map(|f| f.size).collect()),
+        );
+    } else {
+        header.set(
+            IndexTag::RPMTAG_FILESIZES,
+            Value::Int32(files.iter().map(|f| f.size as u32).collect()),
+        );
+    }
+
+    let split: Vec<(&str, &str)> = files
+        .iter()
+        .map(|f| {
+            // the files of source packages have no directory
+            let index = f.path.rfind('/').map_or(0, |index| index + 1);
+            (&f.path[..index], &f.path[index..])
+        })
+        .collect();
+    let dirs: Vec<&str> = split
+        .iter()
+        .map(|(dir, _)| *dir)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let count = files.len();
+
+    let tags = vec![
+        (
+            IndexTag::RPMTAG_FILEMODES,
+            Value::Int16(files.iter().map(|f| f.mode as u16).collect()),
+        ),
+        (IndexTag::RPMTAG_FILERDEVS, Value::Int16(vec![0; count])),
+        (
+            IndexTag::RPMTAG_FILEMTIMES,
+            Value::Int32(files.iter().map(|f| f.mtime).collect()),
+        ),
+        (
+            IndexTag::RPMTAG_FILEDIGESTS,
+            Value::StringArray(files.iter().map(|f| f.digest.clone()).collect()),
+        ),
+        (
+            IndexTag::RPMTAG_FILELINKTOS,
+            Value::StringArray(files.iter().map(|f| f.link_to.clone()).collect()),
+        ),
+        (
+            IndexTag::RPMTAG_FILEFLAGS,
+            Value::Int32(files.iter().map(|f| f.flags).collect()),
+        ),
+        (
+            IndexTag::RPMTAG_FILEUSERNAME,
+            Value::StringArray(vec!["root".to_string(); count]),
+        ),
+        (
+            IndexTag::RPMTAG_FILEGROUPNAME,
+            Value::StringArray(vec!["root".to_string(); count]),
+        ),
+        (IndexTag::RPMTAG_FILEDEVICES, Value::Int32(vec![1; count])),
+        (
+            IndexTag::RPMTAG_FILEINODES,
+            Value::Int32((1..=count as u32).collect()),
+        ),
+        (
+            IndexTag::RPMTAG_FILELANGS,
+            Value::StringArray(vec![String::new(); count]),
+        ),
+        (
+            IndexTag::RPMTAG_FILEDIGESTALGO,
+            Value::Int32(vec![digest_algorithm as u32]),
+        ),
+        // verify everything
+        (
+            IndexTag::RPMTAG_FILEVERIFYFLAGS,
+            Value::Int32(vec![u32::MAX; count]),
+        ),
+        (
+            IndexTag::RPMTAG_DIRINDEXES,
+            Value::Int32(
+                split
+                    .iter()
+                    .map(|(dir, _)| dirs.binary_search(dir).unwrap() as u32)
+                    .collect(),
+            ),
+        ),
+        (
+            IndexTag::RPMTAG_BASENAMES,
+            Value::StringArray(split.iter().map(|(_, base)| base.to_string()).collect()),
+        ),
+        (
+            IndexTag::RPMTAG_DIRNAMES,
+            Value::StringArray(dirs.iter().map(|dir| dir.to_string()).collect()),
+        ),
+    ];
+    for (tag, value) in tags {
+        header.set(tag, value);
+    }
+}
+
+/// Require a feature of rpm itself, the way rpmbuild does for the features a package uses.
+fn add_rpmlib_requirement(header: &mut Header, name: &str, version: &str) {
+    // RPMSENSE_RPMLIB | RPMSENSE_LESS | RPMSENSE_EQUAL
+    const RPMLIB_FLAGS: u32 = (1 << 24) | 0x02 | 0x08;
+    let mut names = header.strings(IndexTag::RPMTAG_REQUIRENAME).to_vec();
+    let mut versions = header.strings(IndexTag::RPMTAG_REQUIREVERSION).to_vec();
+    let mut flags: Vec<u32> = header
+        .ints(IndexTag::RPMTAG_REQUIREFLAGS)
+        .into_iter()
+        .map(|f| f as u32)
+        .collect();
+    names.push(name.to_string());
+    versions.push(version.to_string());
+    flags.push(RPMLIB_FLAGS);
+    header.set(IndexTag::RPMTAG_REQUIRENAME, Value::StringArray(names));
+    header.set(
+        IndexTag::RPMTAG_REQUIREVERSION,
+        Value::StringArray(versions),
+    );
+    header.set(IndexTag::RPMTAG_REQUIREFLAGS, Value::Int32(flags));
+}
+
+/// The content of a single archive entry while it is written.
+enum ArchiveContent<'a, W: Write> {
+    Newc(cpio::newc::Writer<&'a mut W>),
+    Stripped(&'a mut W),
+}
+
+impl<'a, W: Write> ArchiveContent<'a, W> {
+    /// Complete the entry after all `size` bytes of its content were written.
+    fn finish(self, size: u64) -> io::Result<()> {
+        match self {
+            ArchiveContent::Newc(entry) => entry.finish().map(|_| ()),
+            ArchiveContent::Stripped(archive) => {
+                archive.write_all(&[0; 3][..padding(size) as usize])
+            }
+        }
+    }
+}
+
+impl<'a, W: Write> Write for ArchiveContent<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            ArchiveContent::Newc(entry) => entry.write(buf),
+            ArchiveContent::Stripped(archive) => archive.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            ArchiveContent::Newc(entry) => entry.flush(),
+            ArchiveContent::Stripped(archive) => archive.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::PackageInfo;
+
+    fn writer() -> PayloadWriter<Vec<u8>> {
+        let compression: Compression = "none".parse().unwrap();
+        PayloadWriter::new(Vec::new(), compression, rpm::PGPHASHALGO_SHA256 as u64).unwrap()
+    }
+
+    #[test]
+    fn stripped_entries_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("data");
+        std::fs::write(&source, "hello").unwrap();
+
+        let mut payload = writer().large_files();
+        payload
+            .add_file(&source, "/usr/share/app/data", Some(0o100644), 0)
+            .unwrap();
+        payload.add_symlink("/usr/share/app/link", "data").unwrap();
+        let mut header = Header::default();
+        let (archive, archive_size) = payload.finish(&mut header).unwrap();
+        assert_eq!(archive_size, archive.len() as u64);
+
+        // no entry falls back to newc, only the trailer is written that way
+        assert!(archive.starts_with(CPIO_STRIPPED_MAGIC));
+        assert_eq!(
+            archive
+                .windows(CPIO_NEWC_MAGIC.len())
+                .filter(|w| *w == CPIO_NEWC_MAGIC)
+                .count(),
+            1
+        );
+        assert_eq!(header.ints(IndexTag::RPMTAG_LONGFILESIZES), vec![5, 4]);
+        assert!(header.get(IndexTag::RPMTAG_FILESIZES).is_none());
+        assert!(header
+            .strings(IndexTag::RPMTAG_REQUIRENAME)
+            .iter()
+            .any(|name| name == "rpmlib(LargeFiles)"));
+
+        let info = PackageInfo::from_header(&header);
+        let mut entries = Vec::new();
+        let archive = decoder(archive.as_slice()).unwrap();
+        for_each_entry(archive, &info.files, |entry, content| {
+            let mut data = String::new();
+            content.read_to_string(&mut data)?;
+            entries.push((entry.path, entry.mode, entry.size, data));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                (
+                    "./usr/share/app/data".to_string(),
+                    0o100644,
+                    5,
+                    "hello".to_string()
+                ),
+                (
+                    "./usr/share/app/link".to_string(),
+                    0o120777,
+                    4,
+                    "data".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn newc_entries_without_large_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("data");
+        std::fs::write(&source, "hello").unwrap();
+
+        let mut payload = writer();
+        payload
+            .add_file(&source, "/usr/share/app/data", None, 0)
+            .unwrap();
+        let mut header = Header::default();
+        let (archive, _) = payload.finish(&mut header).unwrap();
+        assert!(archive.starts_with(CPIO_NEWC_MAGIC));
+        assert_eq!(header.ints(IndexTag::RPMTAG_FILESIZES), vec![5]);
+        assert!(header.get(IndexTag::RPMTAG_LONGFILESIZES).is_none());
+    }
+
+    #[test]
+    fn file_over_4_gib_needs_large_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("sparse");
+        File::create(&source)
+            .unwrap()
+            .set_len(u32::MAX as u64 + 1)
+            .unwrap();
+
+        let error = writer()
+            .add_file(&source, "/usr/share/app/sparse", None, 0)
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("larger than 4 GiB"));
+    }
+}
@@ -0,0 +1,115 @@
+use crate::cli;
+use crate::metadata::{format_mode, PackageInfo};
+use crate::package::Package;
+use crate::AppError;
+
+/// Print the metadata of the given packages, either human readable or as json.
+pub fn query(matches: &clap::ArgMatches) -> Result<(), AppError> {
+    let packages: Vec<&str> = matches.values_of(cli::PACKAGE_ARG).unwrap().collect();
+    let infos = packages
+        .iter()
+        .map(|path| Package::open(path).map(|pkg| PackageInfo::from_header(&pkg.header)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if matches.is_present(cli::JSON_ARG) {
+        // always an array, so scripts do not depend on the number of packages
+        let json = serde_json::to_string_pretty(&infos)
+            .map_err(|e| app_err!("unable to serialize metadata: {}", e))?;
+        println!("{}", json);
+        return Ok(());
+    }
+
+    for (i, info) in infos.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        print_info(info);
+    }
+    Ok(())
+}
+
+fn print_info(info: &PackageInfo) {
+    println!("Name        : {}", info.name);
+    if let Some(epoch) = info.epoch {
+        println!("Epoch       : {}", epoch);
+    }
+    println!("Version     : {}", info.version);
+    println!("Release     : {}", info.release);
+    println!("Architecture: {}", info.arch);
+    println!("Size        : {}", info.size);
+    println!("License     : {}", info.license);
+    for (label, value) in [
+        ("Group       ", &info.group),
+        ("URL         ", &info.url),
+        ("Vendor      ", &info.vendor),
+        ("Packager    ", &info.packager),
+        ("Distribution", &info.distribution),
+        ("Bug URL     ", &info.bug_url),
+        ("Build Host  ", &info.build_host),
+        ("Source RPM  ", &info.source_rpm),
+    ] {
+        if !value.is_empty() {
+            println!("{}: {}", label, value);
+        }
+    }
+    println!("Summary     : {}", info.summary);
+    println!("Description :");
+    println!("{}", info.description);
+
+    for dependencies in info.dependencies.iter() {
+        println!();
+        println!("{}:", capitalize(dependencies.kind));
+        for dependency in dependencies.entries.iter() {
+            println!("    {}", dependency);
+        }
+    }
+
+    for scriptlet in info.scriptlets.iter() {
+        println!();
+        match &scriptlet.interpreter {
+            Some(interpreter) => println!("{} scriptlet (using {}):", scriptlet.name, interpreter),
+            None => println!("{} scriptlet:", scriptlet.name),
+        }
+        println!("{}", scriptlet.content);
+    }
+
+    if !info.changelog.is_empty() {
+        println!();
+        println!("Changelog:");
+        for entry in info.changelog.iter() {
+            let date = chrono::NaiveDateTime::from_timestamp(entry.time as i64, 0);
+            println!("* {} {}", date.format("%a %b %d %Y"), entry.author);
+            println!("{}", entry.text);
+        }
+    }
+
+    if !info.files.is_empty() {
+        println!();
+        println!("Files:");
+        for file in info.files.iter() {
+            let mut line = format!(
+                "    {} {:<8} {:<8} {:>10} {}",
+                format_mode(file.mode),
+                file.user,
+                file.group,
+                file.size,
+                file.path
+            );
+            if !file.link_to.is_empty() {
+                line.push_str(&format!(" -> {}", file.link_to));
+            }
+            if !file.flags.is_empty() {
+                line.push_str(&format!(" ({})", file.flags.join(", ")));
+            }
+            println!("{}", line);
+        }
+    }
+}
+
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
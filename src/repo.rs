@@ -0,0 +1,567 @@
+//! Generation of yum/dnf repository metadata, like `createrepo` does.
+
+use crate::cli;
+use crate::metadata::{Dependency, PackageInfo};
+use crate::package::{self, Package};
+use crate::AppError;
+
+use quick_xml::events::{BytesStart, Event};
+use rpm::{IndexSignatureTag, IndexTag};
+use sha2::Digest;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+const REPODATA_DIR: &str = "repodata";
+
+/// Metadata of a single package, already rendered for each of the metadata files.
+struct RepoEntry {
+    package_size: u64,
+    file_time: u64,
+    primary: String,
+    filelists: String,
+    other: String,
+}
+
+/// Write `repodata` for all packages found below the given directory.
+pub fn repo(matches: &clap::ArgMatches) -> Result<(), AppError> {
+    let dir = Path::new(matches.value_of(cli::DIRECTORY_ARG).unwrap());
+    let mut paths = Vec::new();
+    find_packages(dir, &mut paths)
+        .map_err(|e| app_err!("unable to scan {} for packages: {}", dir.display(), e))?;
+    paths.sort();
+
+    let mut existing = if matches.is_present(cli::UPDATE_ARG) {
+        read_existing(dir)?
+    } else {
+        HashMap::new()
+    };
+
+    let mut reused = 0;
+    let mut entries = Vec::with_capacity(paths.len());
+    for path in paths.iter() {
+        let href = location(dir, path);
+        let (package_size, file_time) =
+            stat(path).map_err(|e| app_err!("unable to read package {}: {}", path.display(), e))?;
+        match existing.remove(&href) {
+            Some(entry) if entry.package_size == package_size && entry.file_time == file_time => {
+                reused += 1;
+                entries.push(entry);
+            }
+            _ => entries.push(read_entry(dir, path)?),
+        }
+    }
+    write_repodata(dir, &entries)?;
+    println!(
+        "wrote metadata of {} packages to {} ({} reused, {} parsed)",
+        entries.len(),
+        dir.join(REPODATA_DIR).display(),
+        reused,
+        entries.len() - reused
+    );
+    Ok(())
+}
+
+fn find_packages(dir: &Path, paths: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            if path.file_name().is_some_and(|n| n == REPODATA_DIR) {
+                continue;
+            }
+            find_packages(&path, paths)?;
+        } else if path.extension().is_some_and(|e| e == "rpm") {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn read_entry(dir: &Path, path: &Path) -> Result<RepoEntry, AppError> {
+    let pkg = Package::open(path)?;
+    let (header_start, header_end) = package::header_range(&pkg)
+        .map_err(|e| app_err!("invalid package {}: {}", path.display(), e))?;
+    // packages can be larger than the available memory, so they are hashed while read
+    let mut sha256 = sha2::Sha256::new();
+    let package_size = std::fs::File::open(path)
+        .and_then(|mut file| std::io::copy(&mut file, &mut sha256))
+        .map_err(|e| app_err!("unable to read package {}: {}", path.display(), e))?;
+    let pkgid = hex::encode(sha256.finalize());
+    let (_, file_time) = stat(path)?;
+    let info = PackageInfo::from_header(&pkg.header);
+
+    let href = location(dir, path);
+    let archive_size = pkg
+        .signature
+        .int(IndexSignatureTag::RPMSIGTAG_PAYLOADSIZE)
+        .or_else(|| pkg.signature.int(package::RPMSIGTAG_LONGARCHIVESIZE))
+        .or_else(|| pkg.header.int(IndexTag::RPMTAG_LONGARCHIVESIZE))
+        .or_else(|| pkg.header.int(IndexTag::RPMTAG_ARCHIVESIZE))
+        .unwrap_or_default();
+    let version = version_element(&info);
+
+    let mut primary = String::new();
+    primary.push_str("<package type=\"rpm\">\n");
+    primary.push_str(&format!("  <name>{}</name>\n", escape(&info.name)));
+    primary.push_str(&format!("  <arch>{}</arch>\n", escape(&info.arch)));
+    primary.push_str(&format!("  {}\n", version));
+    primary.push_str(&format!(
+        "  <checksum type=\"sha256\" pkgid=\"YES\">{}</checksum>\n",
+        pkgid
+    ));
+    primary.push_str(&format!("  <summary>{}</summary>\n", escape(&info.summary)));
+    primary.push_str(&format!(
+        "  <description>{}</description>\n",
+        escape(&info.description)
+    ));
+    primary.push_str(&format!(
+        "  <packager>{}</packager>\n",
+        escape(&info.packager)
+    ));
+    primary.push_str(&format!("  <url>{}</url>\n", escape(&info.url)));
+    primary.push_str(&format!(
+        "  <time file=\"{}\" build=\"{}\"/>\n",
+        file_time, info.build_time
+    ));
+    primary.push_str(&format!(
+        "  <size package=\"{}\" installed=\"{}\" archive=\"{}\"/>\n",
+        package_size, info.size, archive_size
+    ));
+    primary.push_str(&format!("  <location href=\"{}\"/>\n", escape(&href)));
+    primary.push_str("  <format>\n");
+    for (tag, value) in [
+        ("license", &info.license),
+        ("vendor", &info.vendor),
+        ("group", &info.group),
+        ("buildhost", &info.build_host),
+        ("sourcerpm", &info.source_rpm),
+    ] {
+        primary.push_str(&format!("    <rpm:{0}>{1}</rpm:{0}>\n", tag, escape(value)));
+    }
+    primary.push_str(&format!(
+        "    <rpm:header-range start=\"{}\" end=\"{}\"/>\n",
+        header_start, header_end
+    ));
+    for dependencies in info.dependencies.iter() {
+        // rpmlib() dependencies are satisfied by rpm itself
+        let entries: Vec<&Dependency> = dependencies
+            .entries
+            .iter()
+            .filter(|d| !d.name.starts_with("rpmlib("))
+            .collect();
+        if entries.is_empty() {
+            continue;
+        }
+        primary.push_str(&format!("    <rpm:{}>\n", dependencies.kind));
+        for dependency in entries {
+            primary.push_str(&format!("      {}\n", dependency_element(dependency)));
+        }
+        primary.push_str(&format!("    </rpm:{}>\n", dependencies.kind));
+    }
+    // like createrepo, primary only lists files commonly used in dependencies
+    for file in info.files.iter().filter(|f| is_primary_file(&f.path)) {
+        primary.push_str(&format!("    {}\n", file_element(file)));
+    }
+    primary.push_str("  </format>\n");
+    primary.push_str("</package>\n");
+
+    let package_attributes = format!(
+        "pkgid=\"{}\" name=\"{}\" arch=\"{}\"",
+        pkgid,
+        escape(&info.name),
+        escape(&info.arch)
+    );
+
+    let mut filelists = format!("<package {}>\n  {}\n", package_attributes, version);
+    for file in info.files.iter() {
+        filelists.push_str(&format!("  {}\n", file_element(file)));
+    }
+    filelists.push_str("</package>\n");
+
+    let mut other = format!("<package {}>\n  {}\n", package_attributes, version);
+    for entry in info.changelog.iter() {
+        other.push_str(&format!(
+            "  <changelog author=\"{}\" date=\"{}\">{}</changelog>\n",
+            escape(&entry.author),
+            entry.time,
+            escape(&entry.text)
+        ));
+    }
+    other.push_str("</package>\n");
+
+    Ok(RepoEntry {
+        package_size,
+        file_time,
+        primary,
+        filelists,
+        other,
+    })
+}
+
+/// Location of a package relative to the repository root, as used in `<location href>`.
+fn location(dir: &Path, path: &Path) -> String {
+    path.strip_prefix(dir)
+        .unwrap_or(path)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Size and modification time of a file, the values used to detect changed packages.
+fn stat(path: &Path) -> std::io::Result<(u64, u64)> {
+    let metadata = std::fs::metadata(path)?;
+    let mtime = metadata
+        .modified()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    Ok((metadata.len(), mtime))
+}
+
+/// Read the entries of existing repository metadata, keyed by package location.
+///
+/// Entries are kept as the raw xml they were written as, so metadata of packages
+/// that did not change is carried over without parsing the packages again.
+fn read_existing(dir: &Path) -> Result<HashMap<String, RepoEntry>, AppError> {
+    let repomd_path = dir.join(REPODATA_DIR).join("repomd.xml");
+    let repomd = match std::fs::read_to_string(&repomd_path) {
+        Ok(repomd) => repomd,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(app_err!("unable to read {}: {}", repomd_path.display(), e)),
+    };
+    let locations =
+        parse_repomd(&repomd).map_err(|e| app_err!("invalid {}: {}", repomd_path.display(), e))?;
+    let read = |kind: &str| -> Result<String, AppError> {
+        let href = locations.get(kind).ok_or_else(|| {
+            app_err!(
+                "{} does not reference {} metadata",
+                repomd_path.display(),
+                kind
+            )
+        })?;
+        let path = dir.join(href);
+        let mut content = String::new();
+        flate2::read::GzDecoder::new(std::fs::File::open(&path)?)
+            .read_to_string(&mut content)
+            .map_err(|e| app_err!("unable to read {}: {}", path.display(), e))?;
+        Ok(content)
+    };
+    let by_pkgid = |xml: &str| -> Result<HashMap<String, PackageElement>, AppError> {
+        Ok(package_elements(xml)?
+            .into_iter()
+            .map(|e| (e.pkgid.clone(), e))
+            .collect())
+    };
+    let primary = package_elements(&read("primary")?)?;
+    let mut filelists = by_pkgid(&read("filelists")?)?;
+    let mut other = by_pkgid(&read("other")?)?;
+
+    let mut entries = HashMap::new();
+    for package in primary {
+        let (filelists, other) = match (
+            filelists.remove(&package.pkgid),
+            other.remove(&package.pkgid),
+        ) {
+            (Some(filelists), Some(other)) => (filelists, other),
+            // incomplete metadata, the package is parsed again
+            _ => continue,
+        };
+        entries.insert(
+            package.href,
+            RepoEntry {
+                package_size: package.package_size,
+                file_time: package.file_time,
+                primary: package.xml,
+                filelists: filelists.xml,
+                other: other.xml,
+            },
+        );
+    }
+    Ok(entries)
+}
+
+fn parse_repomd(repomd: &str) -> Result<HashMap<String, String>, AppError> {
+    let mut reader = quick_xml::Reader::from_str(repomd);
+    let mut locations = HashMap::new();
+    let mut kind = None;
+    loop {
+        match reader.read_event().map_err(xml_err)? {
+            Event::Start(e) if e.local_name().as_ref() == b"data" => {
+                kind = attribute(&e, "type")?;
+            }
+            Event::Empty(e) if e.local_name().as_ref() == b"location" => {
+                if let (Some(kind), Some(href)) = (&kind, attribute(&e, "href")?) {
+                    locations.insert(kind.clone(), href);
+                }
+            }
+            Event::End(e) if e.local_name().as_ref() == b"data" => kind = None,
+            Event::Eof => return Ok(locations),
+            _ => {}
+        }
+    }
+}
+
+/// A `<package>` element of one of the metadata files.
+struct PackageElement {
+    pkgid: String,
+    href: String,
+    package_size: u64,
+    file_time: u64,
+    xml: String,
+}
+
+/// Split a metadata file into its `<package>` elements.
+fn package_elements(xml: &str) -> Result<Vec<PackageElement>, AppError> {
+    let mut reader = quick_xml::Reader::from_str(xml);
+    let mut elements = Vec::new();
+    let mut current: Option<(usize, PackageElement)> = None;
+    let mut in_checksum = false;
+    loop {
+        let position = reader.buffer_position() as usize;
+        match reader.read_event().map_err(xml_err)? {
+            Event::Start(e) if e.local_name().as_ref() == b"package" => {
+                let start = xml[position..].find('<').map_or(position, |i| position + i);
+                current = Some((
+                    start,
+                    PackageElement {
+                        pkgid: attribute(&e, "pkgid")?.unwrap_or_default(),
+                        href: String::new(),
+                        package_size: 0,
+                        file_time: 0,
+                        xml: String::new(),
+                    },
+                ));
+            }
+            Event::End(e) if e.local_name().as_ref() == b"package" => {
+                if let Some((start, mut element)) = current.take() {
+                    let end = reader.buffer_position() as usize;
+                    element.xml = format!("{}\n", &xml[start..end]);
+                    elements.push(element);
+                }
+            }
+            Event::Start(e) | Event::Empty(e) => {
+                if let Some((_, element)) = current.as_mut() {
+                    match e.local_name().as_ref() {
+                        b"location" => element.href = attribute(&e, "href")?.unwrap_or_default(),
+                        b"size" => element.package_size = number_attribute(&e, "package")?,
+                        b"time" => element.file_time = number_attribute(&e, "file")?,
+                        b"checksum" => {
+                            in_checksum = attribute(&e, "pkgid")?.as_deref() == Some("YES")
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Event::Text(text) if in_checksum => {
+                if let Some((_, element)) = current.as_mut() {
+                    element.pkgid = text.unescape().map_err(xml_err)?.trim().to_string();
+                }
+            }
+            Event::End(_) => in_checksum = false,
+            Event::Eof => return Ok(elements),
+            _ => {}
+        }
+    }
+}
+
+fn attribute(e: &BytesStart, name: &str) -> Result<Option<String>, AppError> {
+    match e.try_get_attribute(name).map_err(xml_err)? {
+        Some(attribute) => Ok(Some(
+            attribute.unescape_value().map_err(xml_err)?.to_string(),
+        )),
+        None => Ok(None),
+    }
+}
+
+fn number_attribute(e: &BytesStart, name: &str) -> Result<u64, AppError> {
+    let value = attribute(e, name)?.unwrap_or_default();
+    value
+        .parse()
+        .map_err(|_| app_err!("invalid value {:?} of attribute {}", value, name))
+}
+
+fn xml_err<E: std::fmt::Display>(e: E) -> AppError {
+    app_err!("invalid xml: {}", e)
+}
+
+fn write_repodata(dir: &Path, entries: &[RepoEntry]) -> Result<(), AppError> {
+    let count = entries.len();
+    let primary = render(
+        &format!(
+            "<metadata xmlns=\"http://linux.duke.edu/metadata/common\" xmlns:rpm=\"http://linux.duke.edu/metadata/rpm\" packages=\"{}\">\n",
+            count
+        ),
+        entries.iter().map(|e| e.primary.as_str()),
+        "</metadata>\n",
+    );
+    let filelists = render(
+        &format!(
+            "<filelists xmlns=\"http://linux.duke.edu/metadata/filelists\" packages=\"{}\">\n",
+            count
+        ),
+        entries.iter().map(|e| e.filelists.as_str()),
+        "</filelists>\n",
+    );
+    let other = render(
+        &format!(
+            "<otherdata xmlns=\"http://linux.duke.edu/metadata/other\" packages=\"{}\">\n",
+            count
+        ),
+        entries.iter().map(|e| e.other.as_str()),
+        "</otherdata>\n",
+    );
+
+    // write everything next to the old metadata first, clients never see a half written repo
+    let tmp_dir = dir.join(format!(".{}.{}.tmp", REPODATA_DIR, std::process::id()));
+    let result = (|| {
+        std::fs::create_dir_all(&tmp_dir)?;
+        let timestamp = chrono::Utc::now().timestamp();
+        let mut repomd = String::new();
+        repomd.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        repomd.push_str("<repomd xmlns=\"http://linux.duke.edu/metadata/repo\" xmlns:rpm=\"http://linux.duke.edu/metadata/rpm\">\n");
+        repomd.push_str(&format!("  <revision>{}</revision>\n", timestamp));
+        for (kind, content) in [
+            ("primary", &primary),
+            ("filelists", &filelists),
+            ("other", &other),
+        ] {
+            let file_name = format!("{}.xml.gz", kind);
+            let compressed = gzip(content.as_bytes())?;
+            std::fs::write(tmp_dir.join(&file_name), &compressed)?;
+            repomd.push_str(&format!("  <data type=\"{}\">\n", kind));
+            repomd.push_str(&format!(
+                "    <checksum type=\"sha256\">{}</checksum>\n",
+                hex::encode(sha2::Sha256::digest(&compressed))
+            ));
+            repomd.push_str(&format!(
+                "    <open-checksum type=\"sha256\">{}</open-checksum>\n",
+                hex::encode(sha2::Sha256::digest(content.as_bytes()))
+            ));
+            repomd.push_str(&format!(
+                "    <location href=\"{}/{}\"/>\n",
+                REPODATA_DIR, file_name
+            ));
+            repomd.push_str(&format!("    <timestamp>{}</timestamp>\n", timestamp));
+            repomd.push_str(&format!("    <size>{}</size>\n", compressed.len()));
+            repomd.push_str(&format!("    <open-size>{}</open-size>\n", content.len()));
+            repomd.push_str("  </data>\n");
+        }
+        repomd.push_str("</repomd>\n");
+        std::fs::write(tmp_dir.join("repomd.xml"), repomd)?;
+
+        // move the old metadata aside instead of deleting it, so the repository is without
+        // metadata only between the two renames and can be restored if the second one fails
+        let repodata = dir.join(REPODATA_DIR);
+        let old_dir = dir.join(format!(".{}.{}.old", REPODATA_DIR, std::process::id()));
+        let replaced = repodata.exists();
+        if replaced {
+            std::fs::rename(&repodata, &old_dir)?;
+        }
+        if let Err(e) = std::fs::rename(&tmp_dir, &repodata) {
+            if replaced {
+                let _ = std::fs::rename(&old_dir, &repodata);
+            }
+            return Err(e.into());
+        }
+        if replaced {
+            std::fs::remove_dir_all(&old_dir)?;
+        }
+        Ok(())
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_dir_all(&tmp_dir);
+    }
+    result.map_err(|e: AppError| app_err!("unable to write repository metadata: {}", e))
+}
+
+fn render<'a, I: Iterator<Item = &'a str>>(open: &str, packages: I, close: &str) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str(open);
+    packages.for_each(|p| out.push_str(p));
+    out.push_str(close);
+    out
+}
+
+fn gzip(data: &[u8]) -> Result<Vec<u8>, AppError> {
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+fn version_element(info: &PackageInfo) -> String {
+    format!(
+        "<version epoch=\"{}\" ver=\"{}\" rel=\"{}\"/>",
+        info.epoch.unwrap_or_default(),
+        escape(&info.version),
+        escape(&info.release)
+    )
+}
+
+fn dependency_element(dependency: &Dependency) -> String {
+    let name = escape(&dependency.name);
+    let (operator, version) = match (dependency.operator, &dependency.version) {
+        (Some(operator), Some(version)) => (operator, version),
+        _ => return format!("<rpm:entry name=\"{}\"/>", name),
+    };
+    let flags = match operator {
+        "<" => "LT",
+        "<=" => "LE",
+        ">=" => "GE",
+        ">" => "GT",
+        _ => "EQ",
+    };
+    // versions have the form [epoch:]version[-release]
+    let (epoch, rest) = match version.split_once(':') {
+        Some((epoch, rest)) => (epoch, rest),
+        None => ("0", version.as_str()),
+    };
+    let mut element = format!(
+        "<rpm:entry name=\"{}\" flags=\"{}\" epoch=\"{}\"",
+        name,
+        flags,
+        escape(epoch)
+    );
+    match rest.rsplit_once('-') {
+        Some((ver, rel)) => element.push_str(&format!(
+            " ver=\"{}\" rel=\"{}\"/>",
+            escape(ver),
+            escape(rel)
+        )),
+        None => element.push_str(&format!(" ver=\"{}\"/>", escape(rest))),
+    }
+    element
+}
+
+fn file_element(file: &crate::metadata::FileInfo) -> String {
+    let path = escape(&file.path);
+    if file.flags.contains(&"ghost") {
+        format!("<file type=\"ghost\">{}</file>", path)
+    } else if file.mode as u32 & 0o170000 == 0o040000 {
+        format!("<file type=\"dir\">{}</file>", path)
+    } else {
+        format!("<file>{}</file>", path)
+    }
+}
+
+fn is_primary_file(path: &str) -> bool {
+    path.starts_with("/etc/") || path.contains("bin/") || path == "/usr/lib/sendmail"
+}
+
+/// Escape text for xml, dropping characters xml is unable to represent.
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            '\t' | '\n' | '\r' => out.push(c),
+            c if (c as u32) < 0x20 => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
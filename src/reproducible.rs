@@ -0,0 +1,66 @@
+//! Support for reproducible builds, see <https://reproducible-builds.org/specs/source-date-epoch/>.
+
+use crate::cli;
+use crate::header::Value;
+use crate::package::Package;
+use crate::AppError;
+
+use rpm::IndexTag;
+
+/// The build host recorded in reproducible builds, the real one would differ between builders.
+const REPRODUCIBLE_BUILD_HOST: &str = "reproducible";
+
+/// Read the source date epoch from `--source-date-epoch` or `SOURCE_DATE_EPOCH`.
+pub fn source_date_epoch(matches: &clap::ArgMatches) -> Result<Option<u64>, AppError> {
+    match matches.value_of(cli::SOURCE_DATE_EPOCH_ARG) {
+        // an empty variable is treated as unset, like other tools do
+        None | Some("") => Ok(None),
+        Some(raw) => raw.trim().parse().map(Some).map_err(|_e| {
+            app_err!(
+                "invalid source date epoch {}: expected seconds since 1970-01-01",
+                raw
+            )
+        }),
+    }
+}
+
+/// Record build time and host and, if a source date epoch is given, clamp every timestamp to it.
+///
+/// The header has to be updated afterwards.
+pub fn apply(pkg: &mut Package, source_date_epoch: Option<u64>) {
+    let now = chrono::Utc::now().timestamp() as u64;
+    let build_time = source_date_epoch.map_or(now, |epoch| epoch.min(now));
+    pkg.header.set(
+        IndexTag::RPMTAG_BUILDTIME,
+        Value::Int32(vec![build_time as u32]),
+    );
+
+    let build_host = match source_date_epoch {
+        Some(_) => REPRODUCIBLE_BUILD_HOST.to_string(),
+        None => hostname(),
+    };
+    pkg.header
+        .set(IndexTag::RPMTAG_BUILDHOST, Value::String(build_host));
+
+    if let Some(epoch) = source_date_epoch {
+        let mtimes: Vec<u32> = pkg
+            .header
+            .ints(IndexTag::RPMTAG_FILEMTIMES)
+            .into_iter()
+            .map(|mtime| mtime.min(epoch) as u32)
+            .collect();
+        if !mtimes.is_empty() {
+            pkg.header
+                .set(IndexTag::RPMTAG_FILEMTIMES, Value::Int32(mtimes));
+        }
+    }
+}
+
+fn hostname() -> String {
+    ["/proc/sys/kernel/hostname", "/etc/hostname"]
+        .iter()
+        .filter_map(|path| std::fs::read_to_string(path).ok())
+        .map(|name| name.trim().to_string())
+        .find(|name| !name.is_empty())
+        .unwrap_or_else(|| "localhost".to_string())
+}
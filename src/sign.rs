@@ -0,0 +1,19 @@
+use crate::cli;
+use crate::package::Package;
+use crate::signing;
+use crate::AppError;
+
+/// Sign already built packages in place.
+pub fn sign(matches: &clap::ArgMatches) -> Result<(), AppError> {
+    let key_path = matches.value_of(cli::KEY_ARG).unwrap();
+    let passphrase = signing::read_passphrase(matches)?;
+    let signer = signing::load_signer(key_path, passphrase)?;
+
+    for path in matches.values_of(cli::PACKAGE_ARG).unwrap() {
+        let mut pkg = Package::open(path)?;
+        pkg.sign(Some(&signer))
+            .map_err(|e| app_err!("unable to sign package {}: {}", path, e))?;
+        pkg.write_atomic(path)?;
+    }
+    Ok(())
+}
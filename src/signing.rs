@@ -0,0 +1,191 @@
+use crate::cli;
+use crate::AppError;
+
+use pgp::composed::Deserializable;
+use pgp::crypto::hash::HashAlgorithm;
+use pgp::packet::{SignatureConfig, SignatureType, SignatureVersion, Subpacket};
+use pgp::types::{KeyTrait, PublicKeyTrait, SecretKeyTrait};
+use std::io::{Cursor, Read};
+
+/// Signer that is able to unlock passphrase protected secret keys.
+///
+/// `rpm::signature::pgp::Signer` always unlocks the key with an empty passphrase,
+/// so encrypted keys can only be used through this type.
+#[derive(Clone)]
+pub struct Signer {
+    secret_key: pgp::SignedSecretKey,
+    passphrase: String,
+    source_date_epoch: Option<u64>,
+}
+
+impl std::fmt::Debug for Signer {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Signer")
+            .field("key_id", &self.secret_key.key_id())
+            .finish()
+    }
+}
+
+impl rpm::signature::Signing<rpm::signature::algorithm::RSA> for Signer {
+    type Signature = Vec<u8>;
+
+    fn sign<R: Read>(&self, data: R) -> Result<Self::Signature, rpm::RPMError> {
+        let now = self.creation_time();
+        let sig_cfg = SignatureConfig {
+            version: SignatureVersion::V4,
+            typ: SignatureType::Binary,
+            pub_alg: pgp::crypto::public_key::PublicKeyAlgorithm::RSA,
+            hash_alg: HashAlgorithm::SHA2_256,
+            issuer: Some(self.secret_key.key_id()),
+            created: Some(now),
+            unhashed_subpackets: vec![],
+            hashed_subpackets: vec![
+                Subpacket::SignatureCreationTime(now),
+                Subpacket::Issuer(self.secret_key.key_id()),
+            ],
+        };
+
+        let signature_packet = sig_cfg
+            .sign(&self.secret_key, || self.passphrase.clone(), data)
+            .map_err(|e| rpm::RPMError::SignError(Box::new(e)))?;
+
+        let mut signature_bytes = Vec::with_capacity(1024);
+        let mut cursor = Cursor::new(&mut signature_bytes);
+        pgp::packet::write_packet(&mut cursor, &signature_packet)
+            .map_err(|e| rpm::RPMError::SignError(Box::new(e)))?;
+        Ok(signature_bytes)
+    }
+}
+
+impl Signer {
+    /// Clamp the creation time of signatures, RSA signatures are reproducible otherwise.
+    pub fn source_date_epoch(mut self, source_date_epoch: Option<u64>) -> Self {
+        self.source_date_epoch = source_date_epoch;
+        self
+    }
+
+    fn creation_time(&self) -> chrono::DateTime<chrono::Utc> {
+        // the signature format only stores seconds
+        use chrono::TimeZone;
+        let now = chrono::Utc::now().timestamp();
+        let time = match self.source_date_epoch {
+            Some(epoch) => now.min(epoch as i64),
+            None => now,
+        };
+        chrono::Utc.timestamp(time, 0)
+    }
+}
+
+/// Load an ascii armored secret key and unlock it with the given passphrase.
+pub fn load_signer(key_path: &str, passphrase: Option<String>) -> Result<Signer, AppError> {
+    let raw_key = std::fs::read_to_string(key_path).map_err(|e| {
+        app_err!(
+            "unable to load private key file from path {}: {}",
+            key_path,
+            e
+        )
+    })?;
+
+    let secret_key = match pgp::SignedSecretKey::from_string(&raw_key) {
+        Ok((secret_key, _)) => secret_key,
+        Err(e) => {
+            if pgp::SignedPublicKey::from_string(&raw_key).is_ok() {
+                return Err(app_err!(
+                    "{} is a public key, not a secret key. Please provide the secret key for signing",
+                    key_path
+                ));
+            }
+            return Err(app_err!(
+                "unable to create signer from private key {}: not an ascii armored secret key: {}",
+                key_path,
+                e
+            ));
+        }
+    };
+
+    let is_encrypted = secret_key.primary_key.secret_params().is_encrypted();
+    let passphrase = match passphrase {
+        Some(passphrase) => passphrase,
+        None if is_encrypted => {
+            return Err(app_err!(
+                "private key {} is protected by a passphrase, but none was provided. Use --{}, --{} or --{}",
+                key_path,
+                cli::PGP_PASSPHRASE_ENV_ARG,
+                cli::PGP_PASSPHRASE_FD_ARG,
+                cli::PGP_PASSPHRASE_FILE_ARG
+            ))
+        }
+        None => String::new(),
+    };
+
+    if is_encrypted {
+        // Decrypting with a wrong passphrase does not necessarily fail, it might just yield
+        // a garbage key. Signing a probe and verifying it against the public part catches both.
+        let probe = [0u8; 32];
+        let unlocked = secret_key
+            .create_signature(|| passphrase.clone(), HashAlgorithm::SHA2_256, &probe)
+            .and_then(|signature| {
+                secret_key.verify_signature(HashAlgorithm::SHA2_256, &probe, &signature)
+            });
+        if unlocked.is_err() {
+            return Err(app_err!("wrong passphrase for private key {}", key_path));
+        }
+    }
+
+    Ok(Signer {
+        secret_key,
+        passphrase,
+        source_date_epoch: None,
+    })
+}
+
+/// Read the signing key passphrase from the source selected on the command line, if any.
+pub fn read_passphrase(matches: &clap::ArgMatches) -> Result<Option<String>, AppError> {
+    let raw = if let Some(var) = matches.value_of(cli::PGP_PASSPHRASE_ENV_ARG) {
+        std::env::var(var).map_err(|e| {
+            app_err!(
+                "unable to read passphrase from environment variable {}: {}",
+                var,
+                e
+            )
+        })?
+    } else if let Some(fd) = matches.value_of(cli::PGP_PASSPHRASE_FD_ARG) {
+        let fd: i32 = fd
+            .parse()
+            .map_err(|_e| app_err!("invalid passphrase file descriptor {}", fd))?;
+        read_passphrase_fd(fd)?
+    } else if let Some(path) = matches.value_of(cli::PGP_PASSPHRASE_FILE_ARG) {
+        std::fs::read_to_string(path)
+            .map_err(|e| app_err!("unable to read passphrase file {}: {}", path, e))?
+    } else {
+        return Ok(None);
+    };
+    // only strip the line break, whitespace might be part of the passphrase
+    let passphrase = raw.strip_suffix('\n').unwrap_or(&raw);
+    let passphrase = passphrase.strip_suffix('\r').unwrap_or(passphrase);
+    Ok(Some(passphrase.to_string()))
+}
+
+#[cfg(unix)]
+fn read_passphrase_fd(fd: i32) -> Result<String, AppError> {
+    use std::os::unix::io::FromRawFd;
+    // the descriptor belongs to the caller, it must stay open when the file is dropped
+    let mut file = std::mem::ManuallyDrop::new(unsafe { std::fs::File::from_raw_fd(fd) });
+    let mut passphrase = String::new();
+    file.read_to_string(&mut passphrase).map_err(|e| {
+        app_err!(
+            "unable to read passphrase from file descriptor {}: {}",
+            fd,
+            e
+        )
+    })?;
+    Ok(passphrase)
+}
+
+#[cfg(not(unix))]
+fn read_passphrase_fd(fd: i32) -> Result<String, AppError> {
+    Err(app_err!(
+        "reading the passphrase from file descriptor {} is not supported on this platform",
+        fd
+    ))
+}
@@ -0,0 +1,356 @@
+//! A spec file equivalent to the packages described on the command line.
+
+use crate::cli;
+use crate::subpackage::PackageArgs;
+use crate::{arch, compression, reproducible};
+use crate::{AppError, FileEntry, FileKind};
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+
+/// The scriptlet arguments and the spec sections they end up in.
+const SCRIPTLETS: [(&str, &str); 4] = [
+    (cli::PRE_INSTALL_SCRIPTLET_ARG, "%pre"),
+    (cli::POST_INSTALL_SCRIPTLET_ARG, "%post"),
+    (cli::PRE_UNINSTALL_SCRIPTLET_ARG, "%preun"),
+    (cli::POST_UNINSTALL_SCRIPTLET_ARG, "%postun"),
+];
+
+/// The files of a package, common to all architectures and per architecture.
+type PackageFiles<'a, 'b> = (
+    &'a PackageArgs<'b>,
+    Vec<FileEntry>,
+    BTreeMap<String, Vec<FileEntry>>,
+);
+
+/// Generate the spec file building `packages` for `arches`.
+///
+/// Every packaged file is a source of the spec, `%install` copies it from `%{SOURCEn}`.
+pub fn spec(packages: &[PackageArgs], arches: &[String]) -> Result<String, AppError> {
+    let main = &packages[0];
+    let matches = main.matches;
+    let files = files_of(packages)?;
+    let sources = sources_of(main, &files)?;
+    let mut spec = String::new();
+
+    if !matches.is_present(cli::DEBUGINFO_ARG) {
+        spec.push_str("%global debug_package %{nil}\n");
+    }
+    if matches.is_present(cli::NO_BUILD_ID_LINKS_ARG) {
+        spec.push_str("%global _build_id_links none\n");
+    }
+    let compression = compression::compression(matches)?;
+    writeln!(
+        spec,
+        "%global _binary_payload {}",
+        compression.payload_macro()
+    )
+    .unwrap();
+    let file_digest = crate::digest::algorithm(matches.value_of(cli::FILE_DIGEST_ARG).unwrap());
+    writeln!(
+        spec,
+        "%global _binary_filedigest_algorithm {}",
+        file_digest.unwrap()
+    )
+    .unwrap();
+    if let Some(source_date_epoch) = reproducible::source_date_epoch(matches)? {
+        writeln!(spec, "# build with SOURCE_DATE_EPOCH={}", source_date_epoch).unwrap();
+        spec.push_str("%global use_source_date_epoch_as_buildtime 1\n");
+        spec.push_str("%global clamp_mtime_to_source_date_epoch 1\n");
+    }
+    spec.push('\n');
+
+    tag(&mut spec, "Name", &main.name);
+    let epoch = matches.value_of(cli::EPOCH_ARG).unwrap();
+    if epoch != "0" {
+        tag(&mut spec, "Epoch", epoch);
+    }
+    tag(
+        &mut spec,
+        "Version",
+        matches.value_of(cli::VERSION_ARG).unwrap(),
+    );
+    tag(
+        &mut spec,
+        "Release",
+        matches.value_of(cli::RELEASE_ARG).unwrap(),
+    );
+    tag(&mut spec, "Summary", summary(main));
+    tag(
+        &mut spec,
+        "License",
+        matches.value_of(cli::LICENSE_ARG).unwrap(),
+    );
+    for (arg, name) in [
+        (cli::URL_ARG, "URL"),
+        (cli::VENDOR_ARG, "Vendor"),
+        (cli::PACKAGER_ARG, "Packager"),
+        (cli::GROUP_ARG, "Group"),
+        (cli::DISTRIBUTION_ARG, "Distribution"),
+        (cli::BUG_URL_ARG, "BugURL"),
+    ] {
+        if let Some(value) = matches.value_of(arg) {
+            tag(&mut spec, name, value);
+        }
+    }
+    if arches == [arch::NOARCH] {
+        tag(&mut spec, "BuildArch", arch::NOARCH);
+    } else {
+        tag(&mut spec, "ExclusiveArch", &arches.join(" "));
+    }
+    for (index, source) in sources.iter().enumerate() {
+        tag(&mut spec, &format!("Source{}", index), &source.dest);
+    }
+    dependencies(&mut spec, main);
+    writeln!(spec, "\n%description\n{}", escape(&main.description)).unwrap();
+
+    for package in &packages[1..] {
+        let suffix = suffix(main, package);
+        writeln!(spec, "\n%package {}", suffix).unwrap();
+        tag(&mut spec, "Summary", summary(package));
+        dependencies(&mut spec, package);
+        writeln!(
+            spec,
+            "\n%description {}\n{}",
+            suffix,
+            escape(&package.description)
+        )
+        .unwrap();
+    }
+
+    spec.push_str("\n%install\n");
+    for (_, common, by_arch) in &files {
+        for file in common {
+            install(&mut spec, file, &sources)?;
+        }
+        for (arch, arch_files) in by_arch {
+            writeln!(spec, "%ifarch {}", arch).unwrap();
+            for file in arch_files {
+                install(&mut spec, file, &sources)?;
+            }
+            spec.push_str("%endif\n");
+        }
+    }
+
+    for package in packages {
+        for (arg, section) in SCRIPTLETS.iter() {
+            let path = match package.value_of(arg)? {
+                Some(path) => path,
+                None => continue,
+            };
+            let content = std::fs::read_to_string(path)
+                .map_err(|e| app_err!("error reading {} {}: {}", arg, path, e))?;
+            writeln!(spec, "\n{}{}", section, section_suffix(main, package)).unwrap();
+            spec.push_str(&escape(content.trim_end()));
+            spec.push('\n');
+        }
+    }
+
+    for (package, common, by_arch) in &files {
+        writeln!(spec, "\n%files{}", section_suffix(main, package)).unwrap();
+        for file in common {
+            file_line(&mut spec, file)?;
+        }
+        for (arch, arch_files) in by_arch {
+            writeln!(spec, "%ifarch {}", arch).unwrap();
+            for file in arch_files {
+                file_line(&mut spec, file)?;
+            }
+            spec.push_str("%endif\n");
+        }
+    }
+
+    let changelog: Vec<&str> = matches
+        .values_of(cli::CHANGELOG_ARG)
+        .map(|v| v.collect())
+        .unwrap_or_default();
+    if !changelog.is_empty() {
+        spec.push_str("\n%changelog\n");
+    }
+    for raw_entry in changelog {
+        let parts: Vec<&str> = raw_entry.split(':').collect();
+        if parts.len() != 3 {
+            return Err(app_err!(
+                "invalid file argument:{} it needs to be of the form <author>:<content>:<yyyy-mm-dd>",
+                &raw_entry
+            ));
+        }
+        let date = chrono::NaiveDate::parse_from_str(parts[2], "%Y-%m-%d")
+            .map_err(|e| app_err!("error while parsing date time: {}", e))?;
+        writeln!(
+            spec,
+            "* {} {}\n- {}\n",
+            date.format("%a %b %d %Y"),
+            escape(parts[0]),
+            escape(parts[1])
+        )
+        .unwrap();
+    }
+    Ok(spec.trim_end().to_string() + "\n")
+}
+
+/// The name of the spec file of `package`.
+pub fn spec_name(package: &PackageArgs) -> String {
+    format!("{}.spec", package.name)
+}
+
+/// The sources of the spec file, in the order of their `SourceN` tags. The source is the path
+/// of the file on this host, the destination its name in the source rpm.
+pub fn sources(packages: &[PackageArgs]) -> Result<Vec<FileEntry>, AppError> {
+    sources_of(&packages[0], &files_of(packages)?)
+}
+
+fn files_of<'a, 'b>(
+    packages: &'a [PackageArgs<'b>],
+) -> Result<Vec<PackageFiles<'a, 'b>>, AppError> {
+    let mut files = Vec::new();
+    for package in packages {
+        let common = crate::command_line_files(package)?;
+        let by_arch = crate::arch_files_of(package)?;
+        files.push((package, common, by_arch));
+    }
+    Ok(files)
+}
+
+/// The `--source` archives followed by every packaged file, each file once.
+fn sources_of(main: &PackageArgs, files: &[PackageFiles]) -> Result<Vec<FileEntry>, AppError> {
+    let mut paths: Vec<PathBuf> = main
+        .matches
+        .values_of(cli::SOURCE_ARG)
+        .into_iter()
+        .flatten()
+        .map(PathBuf::from)
+        .collect();
+    for (_, common, by_arch) in files {
+        paths.extend(common.iter().map(|file| file.source.clone()));
+        for arch_files in by_arch.values() {
+            paths.extend(arch_files.iter().map(|file| file.source.clone()));
+        }
+    }
+
+    let mut sources: Vec<FileEntry> = Vec::new();
+    for path in paths {
+        let source = canonicalize(&path)?;
+        if sources.iter().any(|known| known.source == source) {
+            continue;
+        }
+        let name = source
+            .file_name()
+            .ok_or_else(|| app_err!("invalid source {}", path.display()))?
+            .to_string_lossy()
+            .to_string();
+        // the source rpm keeps all sources next to each other
+        let name = if name == spec_name(main) || sources.iter().any(|known| known.dest == name) {
+            format!("{}-{}", sources.len(), name)
+        } else {
+            name
+        };
+        sources.push(FileEntry {
+            source,
+            dest: name,
+            kind: FileKind::Regular,
+        });
+    }
+    Ok(sources)
+}
+
+fn canonicalize(path: &std::path::Path) -> Result<PathBuf, AppError> {
+    std::fs::canonicalize(path).map_err(|e| app_err!("error reading {}: {}", path.display(), e))
+}
+
+fn tag(spec: &mut String, name: &str, value: &str) {
+    writeln!(spec, "{:<16}{}", format!("{}:", name), escape(value)).unwrap();
+}
+
+fn dependencies(spec: &mut String, package: &PackageArgs) {
+    for (arg, name) in [
+        (cli::REQUIRES_ARG, "Requires"),
+        (cli::PROVIDES_ARG, "Provides"),
+        (cli::OBSOLETES_ARG, "Obsoletes"),
+        (cli::CONFLICTS_ARG, "Conflicts"),
+    ] {
+        for dependency in package.values_of(arg) {
+            tag(spec, name, dependency.trim());
+        }
+    }
+}
+
+/// The part of the subpackage name following the name of the main package.
+fn suffix<'a>(main: &PackageArgs, package: &'a PackageArgs) -> &'a str {
+    &package.name[main.name.len() + 1..]
+}
+
+/// The argument naming the package of a section, empty for the main package.
+fn section_suffix(main: &PackageArgs, package: &PackageArgs) -> String {
+    if package.name == main.name {
+        String::new()
+    } else {
+        format!(" {}", suffix(main, package))
+    }
+}
+
+/// The mode `file` is installed with.
+fn mode(file: &FileEntry) -> Result<u32, AppError> {
+    let mode = match file.kind.mode() {
+        Some(mode) => mode,
+        None => std::fs::metadata(&file.source)
+            .map_err(|e| app_err!("error reading {}: {}", file.source.display(), e))?
+            .permissions()
+            .mode(),
+    };
+    Ok(mode & 0o7777)
+}
+
+/// rpmbuild refuses packages without a summary.
+fn summary<'a>(package: &'a PackageArgs) -> &'a str {
+    if package.summary.is_empty() {
+        &package.name
+    } else {
+        &package.summary
+    }
+}
+
+fn install(spec: &mut String, file: &FileEntry, sources: &[FileEntry]) -> Result<(), AppError> {
+    let source = canonicalize(&file.source)?;
+    let index = sources
+        .iter()
+        .position(|known| known.source == source)
+        .unwrap();
+    writeln!(
+        spec,
+        "install -D -p -m {:04o} %{{SOURCE{}}} {}",
+        mode(file)?,
+        index,
+        shell_quote(&format!("%{{buildroot}}{}", escape(&file.dest)))
+    )
+    .unwrap();
+    Ok(())
+}
+
+fn file_line(spec: &mut String, file: &FileEntry) -> Result<(), AppError> {
+    let flag = match file.kind {
+        FileKind::Config => "%config ",
+        FileKind::Doc => "%doc ",
+        _ => "",
+    };
+    writeln!(
+        spec,
+        "{}%attr({:04o}, root, root) \"{}\"",
+        flag,
+        mode(file)?,
+        escape(&file.dest)
+    )
+    .unwrap();
+    Ok(())
+}
+
+/// Keep rpm from expanding macros in `text`.
+fn escape(text: &str) -> String {
+    text.replace('%', "%%")
+}
+
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
@@ -0,0 +1,132 @@
+//! Subpackages, built by the same invocation as the main package.
+//!
+//! Files, dependencies, scriptlets, the summary and the description given after
+//! `--subpackage <name>` belong to that subpackage, the ones given before the first
+//! `--subpackage` to the main package. All other arguments are shared by every package.
+
+use crate::cli;
+use crate::description;
+use crate::AppError;
+
+use regex::Regex;
+use std::ops::Range;
+
+/// The arguments of one package of the build.
+pub struct PackageArgs<'a> {
+    pub matches: &'a clap::ArgMatches<'a>,
+    /// The full name of the package, e.g. `myapp-devel`.
+    pub name: String,
+    pub description: String,
+    pub summary: String,
+    /// Whether this is the source package holding the spec file and the sources.
+    pub is_source: bool,
+    /// The command line indices of the arguments belonging to this package.
+    indices: Range<usize>,
+}
+
+/// The main package followed by all subpackages.
+pub fn packages<'a>(matches: &'a clap::ArgMatches<'a>) -> Result<Vec<PackageArgs<'a>>, AppError> {
+    let name = matches.value_of(cli::NAME_ARG).unwrap();
+    let mut starts = vec![(0, name.to_string())];
+    if let (Some(indices), Some(values)) = (
+        matches.indices_of(cli::SUBPACKAGE_ARG),
+        matches.values_of(cli::SUBPACKAGE_ARG),
+    ) {
+        // the characters of dependency names and +, the subpackage name ends up in file names
+        let valid_name = Regex::new(r"^[a-zA-Z0-9\-\._+]+$").unwrap();
+        for (index, subpackage) in indices.zip(values) {
+            if !valid_name.is_match(subpackage) {
+                return Err(app_err!(
+                    "invalid subpackage name \"{}\", only letters, digits and -._+ are allowed",
+                    subpackage
+                ));
+            }
+            let full_name = format!("{}-{}", name, subpackage);
+            if starts.iter().any(|(_, name)| *name == full_name) {
+                return Err(app_err!(
+                    "subpackage {} is given more than once",
+                    subpackage
+                ));
+            }
+            starts.push((index, full_name));
+        }
+    }
+
+    let mut packages: Vec<PackageArgs> = Vec::new();
+    for (i, (start, name)) in starts.iter().enumerate() {
+        let end = starts.get(i + 1).map_or(usize::MAX, |(end, _)| *end);
+        let mut package = PackageArgs {
+            matches,
+            name: name.clone(),
+            description: String::new(),
+            summary: String::new(),
+            is_source: false,
+            indices: *start..end,
+        };
+        // subpackages without a description of their own inherit the one of the main package
+        package.description = match (description::description(&package)?, packages.first()) {
+            (Some(description), _) => description,
+            (None, Some(main)) => main.description.clone(),
+            (None, None) => String::new(),
+        };
+        package.summary =
+            description::summary(package.value_of(cli::SUMMARY_ARG)?, &package.description)?;
+        packages.push(package);
+    }
+    Ok(packages)
+}
+
+impl<'a> PackageArgs<'a> {
+    /// The package the debug information split off this one goes to. It shares all arguments
+    /// but has none of its own.
+    pub fn debuginfo(&self) -> PackageArgs<'a> {
+        PackageArgs {
+            matches: self.matches,
+            name: format!("{}-debuginfo", self.name),
+            description: format!(
+                "This package provides debug information for package {}.",
+                self.name
+            ),
+            summary: format!("Debug information for package {}", self.name),
+            is_source: false,
+            indices: 0..0,
+        }
+    }
+
+    /// The source package of this package. It shares all arguments but has none of its own.
+    pub fn source_package(&self) -> PackageArgs<'a> {
+        PackageArgs {
+            matches: self.matches,
+            name: self.name.clone(),
+            description: self.description.clone(),
+            summary: self.summary.clone(),
+            is_source: true,
+            indices: 0..0,
+        }
+    }
+
+    /// All values of `arg` given for this package.
+    pub fn values_of(&self, arg: &str) -> Vec<&'a str> {
+        match (self.matches.indices_of(arg), self.matches.values_of(arg)) {
+            (Some(indices), Some(values)) => indices
+                .zip(values)
+                .filter(|(index, _)| self.indices.contains(index))
+                .map(|(_, value)| value)
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// The value of `arg` given for this package, if any.
+    pub fn value_of(&self, arg: &str) -> Result<Option<&'a str>, AppError> {
+        match self.values_of(arg).as_slice() {
+            [] => Ok(None),
+            [value] => Ok(Some(value)),
+            _ => Err(app_err!(
+                "--{} is given more than once for {}",
+                arg,
+                self.name
+            )),
+        }
+    }
+}
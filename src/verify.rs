@@ -0,0 +1,269 @@
+use crate::cli;
+use crate::digest;
+use crate::package::{self, Package};
+use crate::AppError;
+
+use rpm::signature::Verifying;
+use rpm::{IndexSignatureTag, IndexTag};
+use sha2::Digest;
+use std::io::Read;
+
+enum Outcome {
+    Passed,
+    Missing,
+    Failed(String),
+}
+
+struct Check {
+    name: &'static str,
+    outcome: Outcome,
+}
+
+/// Verify digests and signatures of the given packages.
+///
+/// Every package is checked, failures are reported at the end.
+pub fn verify(matches: &clap::ArgMatches) -> Result<(), AppError> {
+    let verifiers = matches
+        .values_of(cli::KEY_ARG)
+        .map(|keys| keys.map(load_verifier).collect())
+        .unwrap_or_else(|| Ok(Vec::new()))?;
+
+    let mut failed = 0;
+    let packages: Vec<&str> = matches.values_of(cli::PACKAGE_ARG).unwrap().collect();
+    for path in packages.iter() {
+        let checks = match Package::open(path) {
+            Ok(pkg) => check_package(&pkg, &verifiers),
+            Err(e) => vec![Check {
+                name: "package",
+                outcome: Outcome::Failed(e.to_string()),
+            }],
+        };
+        let passed = checks
+            .iter()
+            .all(|c| !matches!(c.outcome, Outcome::Failed(_)));
+        println!("{}: {}", path, if passed { "OK" } else { "FAILED" });
+        for check in checks {
+            let outcome = match check.outcome {
+                Outcome::Passed => "ok".to_string(),
+                Outcome::Missing => "not present".to_string(),
+                Outcome::Failed(reason) => format!("FAILED ({})", reason),
+            };
+            println!("    {}: {}", check.name, outcome);
+        }
+        if !passed {
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        return Err(app_err!(
+            "{} of {} packages failed verification",
+            failed,
+            packages.len()
+        ));
+    }
+    Ok(())
+}
+
+fn load_verifier(path: &str) -> Result<rpm::signature::pgp::Verifier, AppError> {
+    let raw_key = std::fs::read(path)
+        .map_err(|e| app_err!("unable to load public key file from path {}: {}", path, e))?;
+    rpm::signature::pgp::Verifier::load_from_asc_bytes(&raw_key)
+        .map_err(|e| app_err!("unable to create verifier from public key {}: {}", path, e))
+}
+
+fn check_package(pkg: &Package, verifiers: &[rpm::signature::pgp::Verifier]) -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    checks.push(Check {
+        name: "size",
+        outcome: match (
+            pkg.signature
+                .int(package::RPMSIGTAG_LONGSIZE)
+                .or_else(|| pkg.signature.int(IndexSignatureTag::RPMSIGTAG_SIZE)),
+            pkg.payload.size(),
+        ) {
+            (None, _) => Outcome::Missing,
+            (_, Err(e)) => Outcome::Failed(e.to_string()),
+            (Some(expected), Ok(size)) if expected == pkg.header_bytes.len() as u64 + size => {
+                Outcome::Passed
+            }
+            (Some(expected), Ok(size)) => Outcome::Failed(format!(
+                "expected {}, got {}",
+                expected,
+                pkg.header_bytes.len() as u64 + size
+            )),
+        },
+    });
+
+    checks.push(Check {
+        name: "header SHA1 digest",
+        outcome: compare_digest(
+            pkg.signature.string(IndexSignatureTag::RPMSIGTAG_SHA1),
+            &hex::encode(sha1::Sha1::digest(&pkg.header_bytes)),
+        ),
+    });
+
+    checks.push(Check {
+        name: "header SHA256 digest",
+        outcome: compare_digest(
+            pkg.signature.string(IndexSignatureTag::RPMSIGTAG_SHA256),
+            &hex::encode(sha2::Sha256::digest(&pkg.header_bytes)),
+        ),
+    });
+
+    let md5 = pkg
+        .payload
+        .reader()
+        .and_then(|payload| {
+            digest::hex_digest(
+                rpm::PGPHASHALGO_MD5 as u64,
+                pkg.header_bytes.as_slice().chain(payload),
+            )
+        })
+        .map(Option::unwrap_or_default);
+    checks.push(Check {
+        name: "MD5 digest",
+        outcome: match md5 {
+            Ok(md5) => compare_digest(
+                pkg.signature
+                    .bin(IndexSignatureTag::RPMSIGTAG_MD5)
+                    .map(hex::encode)
+                    .as_deref(),
+                &md5,
+            ),
+            Err(e) => Outcome::Failed(e.to_string()),
+        },
+    });
+
+    checks.push(Check {
+        name: "payload digest",
+        outcome: match pkg.header.string(IndexTag::RPMTAG_PAYLOADDIGEST) {
+            None => Outcome::Missing,
+            Some(expected) => {
+                // sha256 is what rpm uses if the algorithm is not recorded
+                let algorithm = pkg
+                    .header
+                    .int(IndexTag::RPMTAG_PAYLOADDIGESTALGO)
+                    .unwrap_or(rpm::PGPHASHALGO_SHA256 as u64);
+                let actual = pkg
+                    .payload
+                    .reader()
+                    .and_then(|payload| digest::hex_digest(algorithm, payload));
+                match actual {
+                    Ok(Some(actual)) => compare_digest(Some(expected), &actual),
+                    Ok(None) => {
+                        Outcome::Failed(format!("unsupported digest algorithm {}", algorithm))
+                    }
+                    Err(e) => Outcome::Failed(e.to_string()),
+                }
+            }
+        },
+    });
+
+    // single digests may be missing in packages built by other tools, but without any of them
+    // a modified package would pass
+    let no_header_digest = all_missing(&checks, &["header SHA1 digest", "header SHA256 digest"]);
+    let no_payload_digest = all_missing(&checks, &["MD5 digest", "payload digest"]);
+    if no_header_digest {
+        checks.push(Check {
+            name: "header digest",
+            outcome: Outcome::Failed("the package has no header digest".to_string()),
+        });
+    }
+    if no_payload_digest {
+        checks.push(Check {
+            name: "payload digest",
+            outcome: Outcome::Failed("the package has no payload digest".to_string()),
+        });
+    }
+
+    if !verifiers.is_empty() {
+        // rpmsign only writes the header signature, the one over header and payload is optional
+        let header_only = || Ok(pkg.header_bytes.as_slice());
+        let header_and_payload = || Ok(pkg.header_bytes.as_slice().chain(pkg.payload.reader()?));
+        checks.push(Check {
+            name: "header RSA signature",
+            outcome: check_signature(
+                pkg.signature.bin(IndexSignatureTag::RPMSIGTAG_RSA),
+                verifiers,
+                header_only,
+            ),
+        });
+        checks.push(Check {
+            name: "header DSA signature",
+            outcome: check_signature(
+                pkg.signature.bin(IndexSignatureTag::RPMSIGTAG_DSA),
+                verifiers,
+                header_only,
+            ),
+        });
+        checks.push(Check {
+            name: "header and payload signature",
+            outcome: check_signature(
+                pkg.signature.bin(IndexSignatureTag::RPMSIGTAG_PGP),
+                verifiers,
+                header_and_payload,
+            ),
+        });
+        checks.push(Check {
+            name: "header and payload GPG signature",
+            outcome: check_signature(
+                pkg.signature.bin(IndexSignatureTag::RPMSIGTAG_GPG),
+                verifiers,
+                header_and_payload,
+            ),
+        });
+        // signatures are mandatory once a key is given, otherwise unsigned packages would pass
+        if all_missing(&checks, &["header RSA signature", "header DSA signature"]) {
+            checks.push(Check {
+                name: "header signature",
+                outcome: Outcome::Failed("package is not signed".to_string()),
+            });
+        }
+    }
+    checks
+}
+
+/// Whether none of the checks called `names` found anything to check.
+fn all_missing(checks: &[Check], names: &[&str]) -> bool {
+    checks
+        .iter()
+        .filter(|check| names.contains(&check.name))
+        .all(|check| matches!(check.outcome, Outcome::Missing))
+}
+
+fn compare_digest(expected: Option<&str>, actual: &str) -> Outcome {
+    match expected {
+        None => Outcome::Missing,
+        Some(expected) if expected.eq_ignore_ascii_case(actual) => Outcome::Passed,
+        Some(expected) => Outcome::Failed(format!("expected {}, got {}", expected, actual)),
+    }
+}
+
+/// Check `signature` over `data` against every key, it passes if any of them matches.
+fn check_signature<R: Read, F: Fn() -> std::io::Result<R>>(
+    signature: Option<&[u8]>,
+    verifiers: &[rpm::signature::pgp::Verifier],
+    data: F,
+) -> Outcome {
+    let signature = match signature {
+        Some(signature) => signature,
+        None => return Outcome::Missing,
+    };
+    let mut last_error = None;
+    for verifier in verifiers {
+        let data = match data() {
+            Ok(data) => data,
+            Err(e) => return Outcome::Failed(e.to_string()),
+        };
+        match verifier.verify(data, signature) {
+            Ok(()) => return Outcome::Passed,
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Outcome::Failed(format!(
+        "no key matches the signature: {}",
+        last_error.map(|e| e.to_string()).unwrap_or_default()
+    ))
+}
@@ -153,3 +153,1795 @@ fn test_signature() -> Result<(), Box<dyn std::error::Error>> {
     std::fs::remove_dir_all(tmp_dir)?;
     Ok(())
 }
+
+#[test]
+fn test_signature_with_passphrase() -> Result<(), Box<dyn std::error::Error>> {
+    let mut tmp_dir = env::temp_dir();
+    tmp_dir.push("rpm-builder-test-signature-passphrase");
+    fs::create_dir_all(&tmp_dir)?;
+    let mut out_file = tmp_dir.clone();
+    out_file.push("test.rpm");
+
+    let workspace_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let mut rpm_builder_path = workspace_path.clone();
+    rpm_builder_path.push("target/debug/rpm-builder");
+
+    let mut private_key_path = workspace_path.clone();
+    private_key_path.push("tests/test_assets/package-manager-encrypted.key");
+    let mut public_key_path = workspace_path.clone();
+    public_key_path.push("tests/test_assets/package-manager-encrypted.key.pub");
+
+    let build = |passphrase: &str| {
+        Command::new(&rpm_builder_path)
+            .env("RPM_BUILDER_TEST_PASSPHRASE", passphrase)
+            .args(vec![
+                "--file",
+                &format!(
+                    "{}/Cargo.toml:/usr/share/rpm-builder/Cargo.toml",
+                    workspace_path.to_string_lossy()
+                ),
+                "rpm-builder",
+                "-o",
+                &out_file.to_string_lossy(),
+                "--sign-with-pgp-asc",
+                &private_key_path.to_string_lossy(),
+                "--pgp-passphrase-env",
+                "RPM_BUILDER_TEST_PASSPHRASE",
+            ])
+            .output()
+            .expect("failed to execute process")
+    };
+
+    let output = build("not the passphrase");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("wrong passphrase"));
+
+    let output = build("rpm-builder");
+    if !output.stderr.is_empty() {
+        println!("{}", String::from_utf8_lossy(&output.stderr));
+    }
+    assert!(output.status.success());
+
+    let rpm_file = std::fs::File::open(&out_file)?;
+    let mut buffer = std::io::BufReader::new(rpm_file);
+    let pkg = rpm::RPMPackage::parse(&mut buffer)?;
+
+    let raw_public_key = std::fs::read(public_key_path)?;
+    let verifier = rpm::signature::pgp::Verifier::load_from_asc_bytes(&raw_public_key)?;
+    pkg.verify_signature(verifier)?;
+
+    // the passphrase can also be read from a file descriptor the caller passes on
+    let passphrase_file = tmp_dir.join("passphrase");
+    fs::write(&passphrase_file, "rpm-builder\n")?;
+    let output = Command::new(&rpm_builder_path)
+        .stdin(fs::File::open(&passphrase_file)?)
+        .args(vec![
+            "--file",
+            &format!(
+                "{}/Cargo.toml:/usr/share/rpm-builder/Cargo.toml",
+                workspace_path.to_string_lossy()
+            ),
+            "rpm-builder",
+            "-o",
+            &out_file.to_string_lossy(),
+            "--sign-with-pgp-asc",
+            &private_key_path.to_string_lossy(),
+            "--pgp-passphrase-fd",
+            "0",
+        ])
+        .output()
+        .expect("failed to execute process");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let mut buffer = std::io::BufReader::new(std::fs::File::open(&out_file)?);
+    let pkg = rpm::RPMPackage::parse(&mut buffer)?;
+    let verifier = rpm::signature::pgp::Verifier::load_from_asc_bytes(&raw_public_key)?;
+    pkg.verify_signature(verifier)?;
+
+    std::fs::remove_dir_all(tmp_dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_sign_existing_packages() -> Result<(), Box<dyn std::error::Error>> {
+    let mut tmp_dir = env::temp_dir();
+    tmp_dir.push("rpm-builder-test-sign-subcommand");
+    fs::create_dir_all(&tmp_dir)?;
+
+    let workspace_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let mut rpm_builder_path = workspace_path.clone();
+    rpm_builder_path.push("target/debug/rpm-builder");
+
+    let mut private_key_path = workspace_path.clone();
+    private_key_path.push("tests/test_assets/package-manager.key");
+    let mut public_key_path = workspace_path.clone();
+    public_key_path.push("tests/test_assets/package-manager.key.pub");
+
+    let mut packages = Vec::new();
+    for name in ["first", "second"] {
+        let mut out_file = tmp_dir.clone();
+        out_file.push(format!("{}.rpm", name));
+        let output = Command::new(&rpm_builder_path)
+            .args(vec![
+                "--file",
+                &format!(
+                    "{}/Cargo.toml:/usr/share/{}/Cargo.toml",
+                    workspace_path.to_string_lossy(),
+                    name
+                ),
+                name,
+                "-o",
+                &out_file.to_string_lossy(),
+            ])
+            .output()
+            .expect("failed to execute process");
+        assert!(output.status.success());
+        packages.push(out_file);
+    }
+
+    let output = Command::new(&rpm_builder_path)
+        .arg("sign")
+        .arg("--key")
+        .arg(&private_key_path)
+        .args(&packages)
+        .output()
+        .expect("failed to execute process");
+    if !output.stderr.is_empty() {
+        println!("{}", String::from_utf8_lossy(&output.stderr));
+    }
+    assert!(output.status.success());
+
+    let raw_public_key = std::fs::read(public_key_path)?;
+    for package in packages {
+        let rpm_file = std::fs::File::open(&package)?;
+        let mut buffer = std::io::BufReader::new(rpm_file);
+        let pkg = rpm::RPMPackage::parse(&mut buffer)?;
+        let verifier = rpm::signature::pgp::Verifier::load_from_asc_bytes(&raw_public_key)?;
+        pkg.verify_signature(verifier)?;
+    }
+
+    std::fs::remove_dir_all(tmp_dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_verify() -> Result<(), Box<dyn std::error::Error>> {
+    let mut tmp_dir = env::temp_dir();
+    tmp_dir.push("rpm-builder-test-verify");
+    fs::create_dir_all(&tmp_dir)?;
+    let mut out_file = tmp_dir.clone();
+    out_file.push("test.rpm");
+
+    let workspace_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let mut rpm_builder_path = workspace_path.clone();
+    rpm_builder_path.push("target/debug/rpm-builder");
+
+    let mut private_key_path = workspace_path.clone();
+    private_key_path.push("tests/test_assets/package-manager.key");
+    let mut public_key_path = workspace_path.clone();
+    public_key_path.push("tests/test_assets/package-manager.key.pub");
+    let mut other_public_key_path = workspace_path.clone();
+    other_public_key_path.push("tests/test_assets/package-manager-encrypted.key.pub");
+
+    let output = Command::new(&rpm_builder_path)
+        .args(vec![
+            "--file",
+            &format!(
+                "{}/Cargo.toml:/usr/share/rpm-builder/Cargo.toml",
+                workspace_path.to_string_lossy()
+            ),
+            "rpm-builder",
+            "-o",
+            &out_file.to_string_lossy(),
+            "--sign-with-pgp-asc",
+            &private_key_path.to_string_lossy(),
+        ])
+        .output()
+        .expect("failed to execute process");
+    assert!(output.status.success());
+
+    let verify = |key: &PathBuf| {
+        Command::new(&rpm_builder_path)
+            .arg("verify")
+            .arg("--key")
+            .arg(key)
+            .arg(&out_file)
+            .output()
+            .expect("failed to execute process")
+    };
+
+    let output = verify(&public_key_path);
+    if !output.status.success() {
+        println!("{}", String::from_utf8_lossy(&output.stdout));
+    }
+    assert!(output.status.success());
+
+    let output = verify(&other_public_key_path);
+    assert!(!output.status.success());
+
+    // give the signature header entry `from` the tag `to`
+    let retag = |from: u32, to: u32| -> Result<(), Box<dyn std::error::Error>> {
+        let mut raw = fs::read(&out_file)?;
+        let count = u32::from_be_bytes([raw[104], raw[105], raw[106], raw[107]]) as usize;
+        let entry = (0..count)
+            .map(|i| 112 + i * 16)
+            .find(|&entry| raw[entry..entry + 4] == from.to_be_bytes())
+            .expect("tag not found");
+        raw[entry..entry + 4].copy_from_slice(&to.to_be_bytes());
+        fs::write(&out_file, raw)?;
+        Ok(())
+    };
+
+    // rpmsign only writes the header signature, an obsolete tag hides the other one
+    retag(1002, 1001)?;
+    let output = verify(&public_key_path);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("header RSA signature: ok"));
+    assert!(stdout.contains("header and payload signature: not present"));
+
+    // DSA keys sign into a tag of their own
+    retag(268, 267)?;
+    let output = verify(&public_key_path);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("header DSA signature: ok"));
+
+    // without a header signature the package is not signed
+    retag(267, 266)?;
+    let output = verify(&public_key_path);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout)
+        .contains("header signature: FAILED (package is not signed)"));
+
+    // flip the last byte of the payload
+    let mut raw = fs::read(&out_file)?;
+    let last = raw.len() - 1;
+    raw[last] ^= 0xff;
+    fs::write(&out_file, raw)?;
+    let output = verify(&public_key_path);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("MD5 digest: FAILED"));
+
+    std::fs::remove_dir_all(tmp_dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_query() -> Result<(), Box<dyn std::error::Error>> {
+    let mut tmp_dir = env::temp_dir();
+    tmp_dir.push("rpm-builder-test-query");
+    fs::create_dir_all(&tmp_dir)?;
+    let mut out_file = tmp_dir.clone();
+    out_file.push("test.rpm");
+
+    let workspace_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let mut rpm_builder_path = workspace_path.clone();
+    rpm_builder_path.push("target/debug/rpm-builder");
+
+    let output = Command::new(&rpm_builder_path)
+        .args(vec![
+            "--exec-file",
+            &format!(
+                "{}/tests/test_assets/preinst.sh:/usr/bin/preinst",
+                workspace_path.to_string_lossy()
+            ),
+            "--config-file",
+            &format!(
+                "{}/Cargo.toml:/etc/rpm-builder/Cargo.toml",
+                workspace_path.to_string_lossy()
+            ),
+            "--version",
+            "1.2.3",
+            "--license",
+            "MIT",
+            "--requires",
+            "bash>=4.0",
+            "--changelog",
+            "René:initial release:2021-01-01",
+            "--pre-install-script",
+            &format!(
+                "{}/tests/test_assets/preinst.sh",
+                workspace_path.to_string_lossy()
+            ),
+            "rpm-builder",
+            "-o",
+            &out_file.to_string_lossy(),
+        ])
+        .output()
+        .expect("failed to execute process");
+    assert!(output.status.success());
+
+    let output = Command::new(&rpm_builder_path)
+        .arg("query")
+        .arg(&out_file)
+        .output()
+        .expect("failed to execute process");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Name        : rpm-builder"));
+    assert!(stdout.contains("Version     : 1.2.3"));
+    assert!(stdout.contains("bash >= 4.0"));
+    assert!(stdout.contains("-rwxr-xr-x"));
+    assert!(stdout.contains("/etc/rpm-builder/Cargo.toml (config)"));
+
+    let output = Command::new(&rpm_builder_path)
+        .arg("query")
+        .arg("--json")
+        .arg(&out_file)
+        .output()
+        .expect("failed to execute process");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains(r#""name": "rpm-builder""#));
+    assert!(stdout.contains(r#""license": "MIT""#));
+
+    // the json output is an array, no matter how many packages are queried
+    let query_json = |packages: &[&PathBuf]| {
+        let output = Command::new(&rpm_builder_path)
+            .args(vec!["query", "--json"])
+            .args(packages)
+            .output()
+            .expect("failed to execute process");
+        assert!(output.status.success());
+        serde_json::from_slice::<Vec<serde_json::Value>>(&output.stdout).expect("no json array")
+    };
+    let infos = query_json(&[&out_file]);
+    assert_eq!(infos.len(), 1);
+    assert_eq!(infos[0]["name"], "rpm-builder");
+    assert_eq!(query_json(&[&out_file, &out_file]).len(), 2);
+    assert!(stdout.contains(r#""kind": "requires""#));
+    assert!(stdout.contains(r#""name": "preinstall""#));
+    assert!(stdout.contains(r#""author": "René""#));
+    assert!(stdout.contains(r#""path": "/usr/bin/preinst""#));
+
+    std::fs::remove_dir_all(tmp_dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_extract() -> Result<(), Box<dyn std::error::Error>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut tmp_dir = env::temp_dir();
+    tmp_dir.push("rpm-builder-test-extract");
+    fs::create_dir_all(&tmp_dir)?;
+
+    let workspace_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let mut rpm_builder_path = workspace_path.clone();
+    rpm_builder_path.push("target/debug/rpm-builder");
+
+    for compression in ["gzip", "zstd", "none"] {
+        let mut out_file = tmp_dir.clone();
+        out_file.push(format!("test-{}.rpm", compression));
+        let mut extract_dir = tmp_dir.clone();
+        extract_dir.push(compression);
+
+        let output = Command::new(&rpm_builder_path)
+            .args(vec![
+                "--exec-file",
+                &format!(
+                    "{}/tests/test_assets/preinst.sh:/usr/bin/preinst",
+                    workspace_path.to_string_lossy()
+                ),
+                "--dir",
+                &format!(
+                    "{}/tests/test_assets:/usr/share/rpm-builder",
+                    workspace_path.to_string_lossy()
+                ),
+                "--compression",
+                compression,
+                "rpm-builder",
+                "-o",
+                &out_file.to_string_lossy(),
+            ])
+            .output()
+            .expect("failed to execute process");
+        assert!(output.status.success());
+
+        let output = Command::new(&rpm_builder_path)
+            .arg("extract")
+            .arg(&out_file)
+            .arg("-C")
+            .arg(&extract_dir)
+            .output()
+            .expect("failed to execute process");
+        if !output.status.success() {
+            panic!("{} failed: {}", compression, String::from_utf8_lossy(&output.stderr));
+        }
+
+        let extracted = extract_dir.join("usr/bin/preinst");
+        assert_eq!(
+            fs::read(&extracted)?,
+            fs::read(workspace_path.join("tests/test_assets/preinst.sh"))?
+        );
+        assert_eq!(fs::metadata(&extracted)?.permissions().mode() & 0o7777, 0o755);
+        assert_eq!(
+            fs::read(extract_dir.join("usr/share/rpm-builder/package-manager.key"))?,
+            fs::read(workspace_path.join("tests/test_assets/package-manager.key"))?
+        );
+    }
+
+    std::fs::remove_dir_all(tmp_dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_diff() -> Result<(), Box<dyn std::error::Error>> {
+    let mut tmp_dir = env::temp_dir();
+    tmp_dir.push("rpm-builder-test-diff");
+    fs::create_dir_all(&tmp_dir)?;
+    let old_file = tmp_dir.join("old.rpm");
+    let new_file = tmp_dir.join("new.rpm");
+
+    let workspace_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let mut rpm_builder_path = workspace_path.clone();
+    rpm_builder_path.push("target/debug/rpm-builder");
+
+    let build = |out_file: &PathBuf, version: &str, extra: &[&str]| {
+        let output = Command::new(&rpm_builder_path)
+            .args(vec![
+                "--exec-file",
+                &format!(
+                    "{}/tests/test_assets/preinst.sh:/usr/bin/preinst",
+                    workspace_path.to_string_lossy()
+                ),
+                "--version",
+                version,
+                "rpm-builder",
+                "-o",
+                &out_file.to_string_lossy(),
+            ])
+            .args(extra)
+            .output()
+            .expect("failed to execute process");
+        assert!(output.status.success());
+    };
+    build(&old_file, "1.0.0", &[]);
+    build(
+        &new_file,
+        "1.1.0",
+        &[
+            "--requires",
+            "bash>=4.0",
+            "--url",
+            "https://example.com",
+            "--file",
+            &format!(
+                "{}/Cargo.toml:/usr/share/rpm-builder/Cargo.toml",
+                workspace_path.to_string_lossy()
+            ),
+        ],
+    );
+
+    let diff = |extra: &[&str], old: &PathBuf, new: &PathBuf| {
+        Command::new(&rpm_builder_path)
+            .arg("diff")
+            .args(extra)
+            .arg(old)
+            .arg(new)
+            .output()
+            .expect("failed to execute process")
+    };
+
+    let output = diff(&[], &old_file, &old_file);
+    assert_eq!(output.status.code(), Some(0));
+
+    let output = diff(&[], &old_file, &new_file);
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains(r#"version: "1.0.0" -> "1.1.0""#));
+    assert!(stdout.contains("+ requires: bash >= 4.0"));
+    assert!(stdout.contains("+ /usr/share/rpm-builder/Cargo.toml"));
+    assert!(stdout.contains(r#"url: "" -> "https://example.com""#));
+
+    let output = diff(&["--json"], &old_file, &new_file);
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains(r#""status": "added""#));
+    assert!(stdout.contains(r#""tag": "version""#));
+
+    // errors are told apart from differences
+    let output = diff(&[], &old_file, &tmp_dir.join("missing.rpm"));
+    assert_eq!(output.status.code(), Some(2));
+
+    std::fs::remove_dir_all(tmp_dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_repo() -> Result<(), Box<dyn std::error::Error>> {
+    use sha2::Digest;
+
+    let mut tmp_dir = env::temp_dir();
+    tmp_dir.push("rpm-builder-test-repo");
+    let _ = fs::remove_dir_all(&tmp_dir);
+    fs::create_dir_all(tmp_dir.join("x86_64"))?;
+
+    let workspace_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let mut rpm_builder_path = workspace_path.clone();
+    rpm_builder_path.push("target/debug/rpm-builder");
+
+    for (name, out_file) in [("foo", "foo.rpm"), ("bar", "x86_64/bar.rpm")] {
+        let output = Command::new(&rpm_builder_path)
+            .args(vec![
+                "--exec-file",
+                &format!(
+                    "{}/tests/test_assets/preinst.sh:/usr/bin/{}",
+                    workspace_path.to_string_lossy(),
+                    name
+                ),
+                "--changelog",
+                "René:initial release:2021-01-01",
+                name,
+                "-o",
+                &tmp_dir.join(out_file).to_string_lossy(),
+            ])
+            .output()
+            .expect("failed to execute process");
+        assert!(output.status.success());
+    }
+
+    let output = Command::new(&rpm_builder_path)
+        .arg("repo")
+        .arg(&tmp_dir)
+        .output()
+        .expect("failed to execute process");
+    assert!(output.status.success());
+
+    let repodata = tmp_dir.join("repodata");
+    let repomd = fs::read_to_string(repodata.join("repomd.xml"))?;
+    for kind in ["primary", "filelists", "other"] {
+        let compressed = fs::read(repodata.join(format!("{}.xml.gz", kind)))?;
+        assert!(repomd.contains(&hex::encode(sha2::Sha256::digest(&compressed))));
+        assert!(repomd.contains(&format!("repodata/{}.xml.gz", kind)));
+    }
+
+    let mut primary = String::new();
+    std::io::Read::read_to_string(
+        &mut flate2::read::GzDecoder::new(fs::File::open(repodata.join("primary.xml.gz"))?),
+        &mut primary,
+    )?;
+    assert!(primary.contains(r#"packages="2""#));
+    assert!(primary.contains(r#"<location href="x86_64/bar.rpm"/>"#));
+    assert!(primary.contains("<file>/usr/bin/foo</file>"));
+    // the archive size is taken from the signature header
+    assert!(!primary.contains(r#"archive="0""#));
+
+    std::fs::remove_dir_all(tmp_dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_repo_update() -> Result<(), Box<dyn std::error::Error>> {
+    let mut tmp_dir = env::temp_dir();
+    tmp_dir.push("rpm-builder-test-repo-update");
+    let _ = fs::remove_dir_all(&tmp_dir);
+    fs::create_dir_all(&tmp_dir)?;
+
+    let workspace_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let mut rpm_builder_path = workspace_path.clone();
+    rpm_builder_path.push("target/debug/rpm-builder");
+
+    let build = |name: &str, version: &str| {
+        let output = Command::new(&rpm_builder_path)
+            .args(vec![
+                "--exec-file",
+                &format!(
+                    "{}/tests/test_assets/preinst.sh:/usr/bin/{}",
+                    workspace_path.to_string_lossy(),
+                    name
+                ),
+                "--version",
+                version,
+                name,
+                "-o",
+                &tmp_dir.join(format!("{}.rpm", name)).to_string_lossy(),
+            ])
+            .output()
+            .expect("failed to execute process");
+        assert!(output.status.success());
+    };
+    let repo = |extra: &[&str]| {
+        let output = Command::new(&rpm_builder_path)
+            .arg("repo")
+            .args(extra)
+            .arg(&tmp_dir)
+            .output()
+            .expect("failed to execute process");
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+    let metadata = || -> Vec<Vec<u8>> {
+        ["primary", "filelists", "other"]
+            .iter()
+            .map(|kind| {
+                fs::read(tmp_dir.join(format!("repodata/{}.xml.gz", kind))).expect("missing metadata")
+            })
+            .collect()
+    };
+
+    build("foo", "1.0.0");
+    build("bar", "1.0.0");
+    assert!(repo(&["--update"]).contains("(0 reused, 2 parsed)"));
+
+    build("baz", "1.0.0");
+    assert!(repo(&["--update"]).contains("(2 reused, 1 parsed)"));
+
+    build("foo", "1.0.0-with-a-longer-version");
+    fs::remove_file(tmp_dir.join("bar.rpm"))?;
+    assert!(repo(&["--update"]).contains("(1 reused, 1 parsed)"));
+    let updated = metadata();
+
+    // an update has to yield the same metadata as a full run
+    assert!(repo(&[]).contains("(0 reused, 2 parsed)"));
+    assert_eq!(updated, metadata());
+
+    // the old metadata is moved aside and removed once the new one is in place
+    let mut leftovers = fs::read_dir(&tmp_dir)?
+        .map(|e| e.map(|e| e.file_name()))
+        .collect::<Result<Vec<_>, _>>()?;
+    leftovers.retain(|name| name.to_string_lossy().starts_with(".repodata"));
+    assert!(leftovers.is_empty(), "{:?}", leftovers);
+
+    std::fs::remove_dir_all(tmp_dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_reproducible_build() -> Result<(), Box<dyn std::error::Error>> {
+    let mut tmp_dir = env::temp_dir();
+    tmp_dir.push("rpm-builder-test-reproducible");
+    fs::create_dir_all(&tmp_dir)?;
+    let input = tmp_dir.join("input.txt");
+
+    let workspace_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let mut rpm_builder_path = workspace_path.clone();
+    rpm_builder_path.push("target/debug/rpm-builder");
+    let mut private_key_path = workspace_path.clone();
+    private_key_path.push("tests/test_assets/package-manager.key");
+
+    let build = |out_file: &PathBuf, compression: &str| {
+        // rewriting the input changes its mtime, which must not matter
+        fs::write(&input, "reproducible").expect("unable to write input");
+        let output = Command::new(&rpm_builder_path)
+            .env("SOURCE_DATE_EPOCH", "1600000000")
+            .args(vec![
+                "--file",
+                &format!("{}:/usr/share/rpm-builder/input.txt", input.to_string_lossy()),
+                "--dir",
+                &format!(
+                    "{}/tests/test_assets:/usr/share/rpm-builder/assets",
+                    workspace_path.to_string_lossy()
+                ),
+                "--compression",
+                compression,
+                "--sign-with-pgp-asc",
+                &private_key_path.to_string_lossy(),
+                "rpm-builder",
+                "-o",
+                &out_file.to_string_lossy(),
+            ])
+            .output()
+            .expect("failed to execute process");
+        assert!(output.status.success());
+    };
+
+    for compression in ["gzip", "zstd", "none"] {
+        let first = tmp_dir.join(format!("first-{}.rpm", compression));
+        let second = tmp_dir.join(format!("second-{}.rpm", compression));
+        build(&first, compression);
+        // make sure the clock moved on between both builds
+        thread::sleep(std::time::Duration::from_millis(1100));
+        build(&second, compression);
+        assert!(
+            fs::read(&first)? == fs::read(&second)?,
+            "{} builds differ",
+            compression
+        );
+    }
+
+    let output = Command::new(&rpm_builder_path)
+        .args(vec!["query", "--json"])
+        .arg(tmp_dir.join("first-gzip.rpm"))
+        .output()
+        .expect("failed to execute process");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains(r#""build_time": 1600000000"#));
+    assert!(stdout.contains(r#""mtime": 1600000000"#));
+
+    std::fs::remove_dir_all(tmp_dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_argument_order_does_not_matter() -> Result<(), Box<dyn std::error::Error>> {
+    let mut tmp_dir = env::temp_dir();
+    tmp_dir.push("rpm-builder-test-argument-order");
+    fs::create_dir_all(&tmp_dir)?;
+
+    let workspace_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let mut rpm_builder_path = workspace_path.clone();
+    rpm_builder_path.push("target/debug/rpm-builder");
+
+    let cargo_toml = format!("{}/Cargo.toml:/usr/share/rpm-builder/Cargo.toml", workspace_path.to_string_lossy());
+    let readme = format!("{}/README.md:/usr/share/rpm-builder/README.md", workspace_path.to_string_lossy());
+    let assets = format!("{}/tests/test_assets:/usr/share/rpm-builder/assets", workspace_path.to_string_lossy());
+    let build = |out_file: &PathBuf, args: &[&str]| {
+        Command::new(&rpm_builder_path)
+            .args(vec!["--source-date-epoch", "1600000000", "rpm-builder", "-o"])
+            .arg(out_file)
+            .args(args)
+            .output()
+            .expect("failed to execute process")
+    };
+
+    let first = tmp_dir.join("first.rpm");
+    let second = tmp_dir.join("second.rpm");
+    assert!(build(&first, &["--file", &cargo_toml, "--file", &readme, "--dir", &assets]).status.success());
+    assert!(build(&second, &["--dir", &assets, "--file", &readme, "--file", &cargo_toml]).status.success());
+    assert!(fs::read(&first)? == fs::read(&second)?);
+
+    let output = build(&first, &["--file", &cargo_toml, "--doc-file", &cargo_toml]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("are both added as /usr/share/rpm-builder/Cargo.toml"));
+
+    // destinations with and without a leading dot name the same files
+    let relative_cargo_toml = format!("{}/Cargo.toml:./usr/share/rpm-builder/z.toml", workspace_path.to_string_lossy());
+    let output = build(&first, &["--file", &relative_cargo_toml, "--file", &cargo_toml]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let relative_cargo_toml = format!("{}/Cargo.toml:./usr/share/rpm-builder/Cargo.toml", workspace_path.to_string_lossy());
+    let output = build(&first, &["--file", &relative_cargo_toml, "--file", &cargo_toml]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("are both added as /usr/share/rpm-builder/Cargo.toml"));
+
+    std::fs::remove_dir_all(tmp_dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_metadata_tags() -> Result<(), Box<dyn std::error::Error>> {
+    let mut tmp_dir = env::temp_dir();
+    tmp_dir.push("rpm-builder-test-metadata-tags");
+    fs::create_dir_all(&tmp_dir)?;
+    let out_file = tmp_dir.join("test.rpm");
+
+    let workspace_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let mut rpm_builder_path = workspace_path.clone();
+    rpm_builder_path.push("target/debug/rpm-builder");
+
+    let output = Command::new(&rpm_builder_path)
+        .args(vec![
+            "--desc",
+            "A longer description of the package",
+            "--summary",
+            "Build rpms with ease",
+            "--url",
+            "https://github.com/Richterrettich/rpm-builder",
+            "--vendor",
+            "ACME",
+            "--packager",
+            "Jane Doe <jane@example.com>",
+            "--group",
+            "Development/Tools",
+            "--distribution",
+            "ACME Linux",
+            "--bugurl",
+            "https://github.com/Richterrettich/rpm-builder/issues",
+            "rpm-builder",
+            "-o",
+            &out_file.to_string_lossy(),
+        ])
+        .output()
+        .expect("failed to execute process");
+    assert!(output.status.success());
+
+    let output = Command::new(&rpm_builder_path)
+        .args(vec!["query", "--json"])
+        .arg(&out_file)
+        .output()
+        .expect("failed to execute process");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    for expected in [
+        r#""summary": "Build rpms with ease""#,
+        r#""description": "A longer description of the package""#,
+        r#""url": "https://github.com/Richterrettich/rpm-builder""#,
+        r#""vendor": "ACME""#,
+        r#""packager": "Jane Doe <jane@example.com>""#,
+        r#""group": "Development/Tools""#,
+        r#""distribution": "ACME Linux""#,
+        r#""bug_url": "https://github.com/Richterrettich/rpm-builder/issues""#,
+    ] {
+        assert!(stdout.contains(expected), "missing {}", expected);
+    }
+
+    std::fs::remove_dir_all(tmp_dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_description_file() -> Result<(), Box<dyn std::error::Error>> {
+    let mut tmp_dir = env::temp_dir();
+    tmp_dir.push("rpm-builder-test-description-file");
+    fs::create_dir_all(&tmp_dir)?;
+    let out_file = tmp_dir.join("test.rpm");
+    let description_file = tmp_dir.join("description.md");
+    fs::write(
+        &description_file,
+        "# rpm-builder\n\nBuild **rpms** with _ease_, see [the docs](https://example.com).\n\n* no `rpmbuild` needed\n",
+    )?;
+
+    let workspace_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let mut rpm_builder_path = workspace_path.clone();
+    rpm_builder_path.push("target/debug/rpm-builder");
+
+    let build = |args: &[&str]| {
+        Command::new(&rpm_builder_path)
+            .args(args)
+            .args(vec!["rpm-builder", "-o", &out_file.to_string_lossy()])
+            .output()
+            .expect("failed to execute process")
+    };
+
+    let output = build(&[
+        "--description-file",
+        &description_file.to_string_lossy(),
+        "--summary",
+        "Build rpms with ease",
+    ]);
+    assert!(output.status.success());
+    let output = Command::new(&rpm_builder_path)
+        .args(vec!["query", "--json"])
+        .arg(&out_file)
+        .output()
+        .expect("failed to execute process");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains(r#""summary": "Build rpms with ease""#));
+    assert!(stdout.contains(
+        r#""description": "rpm-builder\n\nBuild rpms with ease, see the docs (https://example.com).\n\n- no rpmbuild needed""#
+    ));
+
+    let output = build(&["--summary", "Build rpms with ease."]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("must not end with a period"));
+
+    let output = build(&["--summary", "Build rpms\nwith ease"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("single line"));
+
+    let output = build(&["--desc", "foo", "--description-file", &description_file.to_string_lossy()]);
+    assert!(!output.status.success());
+
+    std::fs::remove_dir_all(tmp_dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_compression_levels() -> Result<(), Box<dyn std::error::Error>> {
+    let mut tmp_dir = env::temp_dir();
+    tmp_dir.push("rpm-builder-test-compression-levels");
+    fs::create_dir_all(&tmp_dir)?;
+    let out_file = tmp_dir.join("test.rpm");
+
+    let workspace_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let cargo_toml = workspace_path.join("Cargo.toml");
+    let rpm_builder_path = workspace_path.join("target/debug/rpm-builder");
+
+    for (compression, compressor) in [
+        ("gzip:1", "gzip"),
+        ("zstd:3", "zstd"),
+        ("xz:9", "xz"),
+        ("lzma", "lzma"),
+        ("bzip2:5", "bzip2"),
+    ] {
+        let output = Command::new(&rpm_builder_path)
+            .args(vec![
+                "--file",
+                &format!("{}:/foo/Cargo.toml", cargo_toml.to_string_lossy()),
+                "--compression",
+                compression,
+                "rpm-builder",
+                "-o",
+                &out_file.to_string_lossy(),
+            ])
+            .output()?;
+        assert!(output.status.success(), "{} failed", compression);
+
+        let mut buffer = std::io::BufReader::new(fs::File::open(&out_file)?);
+        let pkg = rpm::RPMPackage::parse(&mut buffer)?;
+        assert_eq!(pkg.metadata.header.get_payload_compressor()?, compressor);
+
+        let extract_dir = tmp_dir.join(compressor);
+        let output = Command::new(&rpm_builder_path)
+            .args(vec!["extract", &out_file.to_string_lossy(), "-C"])
+            .arg(&extract_dir)
+            .output()?;
+        assert!(output.status.success(), "extracting {} failed", compression);
+        assert_eq!(
+            fs::read(extract_dir.join("foo/Cargo.toml"))?,
+            fs::read(&cargo_toml)?
+        );
+    }
+
+    for invalid in ["xz:10", "gzip:0", "brotli", "none:1"] {
+        let output = Command::new(&rpm_builder_path)
+            .args(vec!["--compression", invalid, "rpm-builder", "-o"])
+            .arg(&out_file)
+            .output()?;
+        assert!(!output.status.success(), "{} was accepted", invalid);
+    }
+
+    std::fs::remove_dir_all(tmp_dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_threaded_compression() -> Result<(), Box<dyn std::error::Error>> {
+    let mut tmp_dir = env::temp_dir();
+    tmp_dir.push("rpm-builder-test-threaded-compression");
+    fs::create_dir_all(&tmp_dir)?;
+
+    let workspace_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let rpm_builder_path = workspace_path.join("target/debug/rpm-builder");
+
+    let build = |compression: &str, threads: &str, out_file: &PathBuf| {
+        Command::new(&rpm_builder_path)
+            .args(vec![
+                "--dir",
+                &format!("{}/src:/src", workspace_path.to_string_lossy()),
+                "--compression",
+                compression,
+                "--threads",
+                threads,
+                "--source-date-epoch",
+                "1600000000",
+                "rpm-builder",
+                "-o",
+                &out_file.to_string_lossy(),
+            ])
+            .output()
+            .expect("failed to execute process")
+    };
+
+    for compression in ["zstd:3", "xz:1"] {
+        let first = tmp_dir.join("first.rpm");
+        let second = tmp_dir.join("second.rpm");
+        assert!(build(compression, "2", &first).status.success());
+        assert!(build(compression, "2", &second).status.success());
+        assert_eq!(fs::read(&first)?, fs::read(&second)?, "{} is not reproducible", compression);
+
+        // rpm records only the level in the payload flags, the thread count is left to rpmbuild
+        let level = &compression[compression.len() - 1..];
+        let flags = format!("{}T2\0", level);
+        let raw = fs::read(&first)?;
+        assert!(!raw.windows(flags.len()).any(|w| w == flags.as_bytes()));
+        let output = Command::new(&rpm_builder_path)
+            .args(vec!["--emit-spec", "--compression", compression])
+            .args(vec!["--threads", "2", "rpm-builder"])
+            .output()?;
+        assert!(String::from_utf8_lossy(&output.stdout)
+            .contains(&format!("%global _binary_payload w{}T2.", level)));
+
+        let output = Command::new(&rpm_builder_path)
+            .args(vec!["extract", &first.to_string_lossy(), "-C"])
+            .arg(tmp_dir.join("extracted"))
+            .output()?;
+        assert!(output.status.success());
+    }
+    assert_eq!(
+        fs::read(tmp_dir.join("extracted/src/compression.rs"))?,
+        fs::read(workspace_path.join("src/compression.rs"))?
+    );
+
+    let output = build("gzip", "2", &tmp_dir.join("gzip.rpm"));
+    assert!(!output.status.success());
+    let output = build("zstd", "0", &tmp_dir.join("zero.rpm"));
+    assert!(!output.status.success());
+
+    std::fs::remove_dir_all(tmp_dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_streaming_build() -> Result<(), Box<dyn std::error::Error>> {
+    let mut tmp_dir = env::temp_dir();
+    tmp_dir.push("rpm-builder-test-streaming-build");
+    let _ = fs::remove_dir_all(&tmp_dir);
+    let out_dir = tmp_dir.join("out");
+    fs::create_dir_all(&out_dir)?;
+    let out_file = out_dir.join("test.rpm");
+
+    // larger than the memory the build is allowed to use
+    let big_file = tmp_dir.join("big");
+    fs::File::create(&big_file)?.set_len(48 * 1024 * 1024)?;
+
+    let workspace_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let rpm_builder_path = workspace_path.join("target/debug/rpm-builder");
+
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(format!(
+            "ulimit -v 40000 && exec {} --file {}:/data/big --compression gzip -o {} rpm-builder",
+            rpm_builder_path.display(),
+            big_file.display(),
+            out_file.display()
+        ))
+        .output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    // the temporary payload is gone
+    assert_eq!(fs::read_dir(&out_dir)?.count(), 1);
+
+    let output = Command::new(&rpm_builder_path)
+        .arg("verify")
+        .arg(&out_file)
+        .output()?;
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("payload digest: ok"));
+
+    let output = Command::new(&rpm_builder_path)
+        .args(vec!["extract", &out_file.to_string_lossy(), "-C"])
+        .arg(tmp_dir.join("extracted"))
+        .output()?;
+    assert!(output.status.success());
+    assert_eq!(
+        fs::metadata(tmp_dir.join("extracted/data/big"))?.len(),
+        48 * 1024 * 1024
+    );
+
+    std::fs::remove_dir_all(tmp_dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_file_digest() -> Result<(), Box<dyn std::error::Error>> {
+    use sha2::Digest;
+
+    let mut tmp_dir = env::temp_dir();
+    tmp_dir.push("rpm-builder-test-file-digest");
+    fs::create_dir_all(&tmp_dir)?;
+    let out_file = tmp_dir.join("test.rpm");
+
+    let workspace_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let cargo_toml = workspace_path.join("Cargo.toml");
+    let rpm_builder_path = workspace_path.join("target/debug/rpm-builder");
+    let content = fs::read(&cargo_toml)?;
+
+    for (args, expected) in [
+        (vec![], hex::encode(sha2::Sha256::digest(&content))),
+        (
+            vec!["--file-digest", "sha512"],
+            hex::encode(sha2::Sha512::digest(&content)),
+        ),
+    ] {
+        let output = Command::new(&rpm_builder_path)
+            .args(args)
+            .args(vec![
+                "--file",
+                &format!("{}:/foo/Cargo.toml", cargo_toml.to_string_lossy()),
+                "rpm-builder",
+                "-o",
+                &out_file.to_string_lossy(),
+            ])
+            .output()?;
+        assert!(output.status.success());
+
+        let output = Command::new(&rpm_builder_path)
+            .args(vec!["query", "--json", &out_file.to_string_lossy()])
+            .output()?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains(&format!(r#""digest": "{}""#, expected)));
+    }
+
+    let output = Command::new(&rpm_builder_path)
+        .args(vec!["--file-digest", "md5", "rpm-builder", "-o"])
+        .arg(&out_file)
+        .output()?;
+    assert!(!output.status.success());
+
+    std::fs::remove_dir_all(tmp_dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_arch() -> Result<(), Box<dyn std::error::Error>> {
+    let mut tmp_dir = env::temp_dir();
+    tmp_dir.push("rpm-builder-test-arch");
+    fs::create_dir_all(&tmp_dir)?;
+    let out_file = tmp_dir.join("test.rpm");
+
+    // just the identification and machine type of a little endian 64 bit aarch64 binary
+    let binary = tmp_dir.join("binary");
+    let mut header = b"\x7fELF\x02\x01\x01".to_vec();
+    header.resize(18, 0);
+    header.extend_from_slice(&183u16.to_le_bytes());
+    fs::write(&binary, header)?;
+
+    let workspace_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let rpm_builder_path = workspace_path.join("target/debug/rpm-builder");
+    let build = |args: Vec<&str>| {
+        Command::new(&rpm_builder_path)
+            .args(args)
+            .args(vec!["rpm-builder", "-o", &out_file.to_string_lossy()])
+            .output()
+    };
+    let exec_file = format!("{}:/usr/bin/binary", binary.to_string_lossy());
+
+    let output = build(vec!["--arch", "x86-64"])?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("did you mean x86_64?"));
+
+    let output = build(vec!["--exec-file", &exec_file])?;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("ELF binary for aarch64, which does not match the architecture x86_64"));
+
+    let output = build(vec!["--arch", "aarch64", "--exec-file", &exec_file])?;
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+
+    // only executables are checked against the architecture
+    let output = build(vec!["--file", &exec_file])?;
+    assert!(output.status.success());
+
+    let output = build(vec!["--arch", "noarch", "--file", &exec_file])?;
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("is an ELF binary, but the package is noarch"));
+
+    std::fs::remove_dir_all(tmp_dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_exec_file_for() -> Result<(), Box<dyn std::error::Error>> {
+    let mut tmp_dir = env::temp_dir();
+    tmp_dir.push("rpm-builder-test-exec-file-for");
+    fs::create_dir_all(&tmp_dir)?;
+
+    let mut binaries = Vec::new();
+    for (arch, machine) in [("x86_64", 62u16), ("aarch64", 183u16)] {
+        let binary = tmp_dir.join(arch);
+        let mut header = b"\x7fELF\x02\x01\x01".to_vec();
+        header.resize(18, 0);
+        header.extend_from_slice(&machine.to_le_bytes());
+        fs::write(&binary, header)?;
+        binaries.push((arch, binary));
+    }
+
+    let workspace_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let cargo_toml = workspace_path.join("Cargo.toml");
+    let rpm_builder_path = workspace_path.join("target/debug/rpm-builder");
+    let mut command = Command::new(&rpm_builder_path);
+    command.current_dir(&tmp_dir).args(vec![
+        "--file",
+        &format!("{}:/usr/share/app/Cargo.toml", cargo_toml.to_string_lossy()),
+        "--version",
+        "2.0.0",
+        "--release",
+        "3",
+    ]);
+    for (arch, binary) in &binaries {
+        command
+            .arg("--exec-file-for")
+            .arg(arch)
+            .arg(format!("{}:/usr/bin/app", binary.to_string_lossy()));
+    }
+    let output = command.arg("app").output()?;
+    assert!(output.status.success());
+
+    for (arch, _) in &binaries {
+        let rpm_file = tmp_dir.join(format!("app-2.0.0-3.{}.rpm", arch));
+        let output = Command::new(&rpm_builder_path)
+            .arg("query")
+            .arg(&rpm_file)
+            .output()?;
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains(&format!("Architecture: {}", arch)));
+        assert!(stdout.contains("/usr/bin/app"));
+        assert!(stdout.contains("/usr/share/app/Cargo.toml"));
+    }
+
+    // the binaries are checked against the architecture they are given for
+    let output = Command::new(&rpm_builder_path)
+        .current_dir(&tmp_dir)
+        .arg("--exec-file-for")
+        .arg("aarch64")
+        .arg(format!("{}:/usr/bin/app", binaries[0].1.to_string_lossy()))
+        .arg("app")
+        .output()?;
+    assert!(!output.status.success());
+
+    let output = Command::new(&rpm_builder_path)
+        .current_dir(&tmp_dir)
+        .args(vec!["--arch", "aarch64", "--exec-file-for", "aarch64"])
+        .arg(format!("{}:/usr/bin/app", binaries[1].1.to_string_lossy()))
+        .arg("app")
+        .output()?;
+    assert!(!output.status.success());
+
+    std::fs::remove_dir_all(tmp_dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_output_naming() -> Result<(), Box<dyn std::error::Error>> {
+    let mut tmp_dir = env::temp_dir();
+    tmp_dir.push("rpm-builder-test-output-naming");
+    fs::create_dir_all(&tmp_dir)?;
+
+    let workspace_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let rpm_builder_path = workspace_path.join("target/debug/rpm-builder");
+    let build = |args: Vec<&str>| {
+        Command::new(&rpm_builder_path)
+            .current_dir(&tmp_dir)
+            .args(vec!["--version", "2.0.0", "--release", "3", "--epoch", "1"])
+            .args(args)
+            .arg("app")
+            .output()
+    };
+
+    let output = build(vec![])?;
+    assert!(output.status.success());
+    assert!(tmp_dir.join("app-2.0.0-3.x86_64.rpm").exists());
+
+    let output = build(vec!["--out-dir", "dist/rpms", "--arch", "noarch"])?;
+    assert!(output.status.success());
+    assert!(tmp_dir.join("dist/rpms/app-2.0.0-3.noarch.rpm").exists());
+
+    let output = build(vec![
+        "--out-dir",
+        "dist",
+        "--out",
+        "{name}-{epoch}:{version}.{arch}.rpm",
+    ])?;
+    assert!(output.status.success());
+    assert!(tmp_dir.join("dist/app-1:2.0.0.x86_64.rpm").exists());
+
+    let output = build(vec!["--out", "{name}-{git}.rpm"])?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown placeholder {git}"));
+
+    // packages for several architectures must not overwrite each other
+    let output = build(vec![
+        "--out",
+        "{name}.rpm",
+        "--exec-file-for",
+        "x86_64",
+        "/dev/null:/usr/bin/app",
+        "--exec-file-for",
+        "aarch64",
+        "/dev/null:/usr/bin/app",
+    ])?;
+    assert!(!output.status.success());
+
+    std::fs::remove_dir_all(tmp_dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_subpackages() -> Result<(), Box<dyn std::error::Error>> {
+    let mut tmp_dir = env::temp_dir();
+    tmp_dir.push("rpm-builder-test-subpackages");
+    fs::create_dir_all(&tmp_dir)?;
+
+    let workspace_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let cargo_toml = workspace_path.join("Cargo.toml");
+    let readme = workspace_path.join("README.md");
+    let preinst = workspace_path.join("tests/test_assets/preinst.sh");
+    let rpm_builder_path = workspace_path.join("target/debug/rpm-builder");
+
+    let output = Command::new(&rpm_builder_path)
+        .current_dir(&tmp_dir)
+        .args(vec![
+            "--version",
+            "2.0.0",
+            "--license",
+            "Apache-2.0",
+            "--desc",
+            "My awesome app",
+            "--file",
+            &format!(
+                "{}:/usr/share/myapp/Cargo.toml",
+                cargo_toml.to_string_lossy()
+            ),
+            "--requires",
+            "wget",
+            "--pre-install-script",
+            &preinst.to_string_lossy(),
+            "--subpackage",
+            "devel",
+            "--summary",
+            "Development files of myapp",
+            "--file",
+            &format!("{}:/usr/include/myapp.h", cargo_toml.to_string_lossy()),
+            "--requires",
+            "myapp = 2.0.0",
+            "--subpackage",
+            "doc",
+            "--doc-file",
+            &format!(
+                "{}:/usr/share/doc/myapp/README.md",
+                readme.to_string_lossy()
+            ),
+            "myapp",
+        ])
+        .output()?;
+    assert!(output.status.success());
+
+    let query = |name: &str| -> Result<String, Box<dyn std::error::Error>> {
+        let output = Command::new(&rpm_builder_path)
+            .arg("query")
+            .arg(tmp_dir.join(format!("{}-2.0.0-1.x86_64.rpm", name)))
+            .output()?;
+        assert!(output.status.success());
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    };
+
+    let main = query("myapp")?;
+    assert!(main.contains("License     : Apache-2.0"));
+    assert!(main.contains("Summary     : My awesome app"));
+    assert!(main.contains("/usr/share/myapp/Cargo.toml"));
+    assert!(main.contains("    wget"));
+    assert!(main.contains("preinstall scriptlet"));
+    assert!(!main.contains("/usr/include/myapp.h"));
+
+    let devel = query("myapp-devel")?;
+    assert!(devel.contains("Name        : myapp-devel"));
+    assert!(devel.contains("License     : Apache-2.0"));
+    assert!(devel.contains("Summary     : Development files of myapp"));
+    assert!(devel.contains("My awesome app"));
+    assert!(devel.contains("/usr/include/myapp.h"));
+    assert!(devel.contains("    myapp ="));
+    assert!(!devel.contains("    wget"));
+    assert!(!devel.contains("preinstall scriptlet"));
+    assert!(!devel.contains("/usr/share/myapp/Cargo.toml"));
+
+    let doc = query("myapp-doc")?;
+    assert!(doc.contains("Summary     : My awesome app"));
+    assert!(doc.contains("/usr/share/doc/myapp/README.md"));
+
+    let output = Command::new(&rpm_builder_path)
+        .current_dir(&tmp_dir)
+        .args(vec![
+            "--subpackage",
+            "doc",
+            "--summary",
+            "a",
+            "--summary",
+            "b",
+            "myapp",
+        ])
+        .output()?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("more than once for myapp-doc"));
+
+    let output = Command::new(&rpm_builder_path)
+        .current_dir(&tmp_dir)
+        .args(vec!["--subpackage", "a/b", "myapp"])
+        .output()?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("invalid subpackage name \"a/b\""));
+
+    std::fs::remove_dir_all(tmp_dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_debuginfo() -> Result<(), Box<dyn std::error::Error>> {
+    let mut tmp_dir = env::temp_dir();
+    tmp_dir.push("rpm-builder-test-debuginfo");
+    fs::create_dir_all(&tmp_dir)?;
+
+    let workspace_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let cargo_toml = workspace_path.join("Cargo.toml");
+    let rpm_builder_path = workspace_path.join("target/debug/rpm-builder");
+
+    let output = Command::new(&rpm_builder_path)
+        .current_dir(&tmp_dir)
+        .args(vec![
+            "--debuginfo",
+            "--exec-file",
+            &format!(
+                "{}:/usr/bin/rpm-builder",
+                rpm_builder_path.to_string_lossy()
+            ),
+            "--file",
+            &format!(
+                "{}:/usr/share/rpm-builder/Cargo.toml",
+                cargo_toml.to_string_lossy()
+            ),
+            "rpm-builder",
+        ])
+        .output()?;
+    assert!(output.status.success());
+
+    let extract_dir = tmp_dir.join("extracted");
+    for rpm_file in [
+        "rpm-builder-1.0.0-1.x86_64.rpm",
+        "rpm-builder-debuginfo-1.0.0-1.x86_64.rpm",
+    ] {
+        let output = Command::new(&rpm_builder_path)
+            .arg("extract")
+            .arg(tmp_dir.join(rpm_file))
+            .arg("-C")
+            .arg(&extract_dir)
+            .output()?;
+        assert!(output.status.success());
+    }
+
+    let stripped = fs::read(extract_dir.join("usr/bin/rpm-builder"))?;
+    let debug = fs::read(extract_dir.join("usr/lib/debug/usr/bin/rpm-builder.debug"))?;
+    let contains =
+        |haystack: &[u8], needle: &[u8]| haystack.windows(needle.len()).any(|w| w == needle);
+    // the section names also appear in the symbolizer of std, so only the size tells
+    assert!(stripped.len() < fs::metadata(&rpm_builder_path)?.len() as usize / 2);
+    assert!(contains(&stripped, b".gnu_debuglink"));
+    assert!(contains(&stripped, b"rpm-builder.debug\0"));
+    assert!(contains(&debug, b".debug_info"));
+    assert_eq!(
+        fs::read(extract_dir.join("usr/share/rpm-builder/Cargo.toml"))?,
+        fs::read(&cargo_toml)?
+    );
+
+    let build_ids: Vec<_> = fs::read_dir(extract_dir.join("usr/lib/debug/.build-id"))?
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    assert_eq!(build_ids.len(), 1);
+    let links: Vec<_> = fs::read_dir(&build_ids[0])?
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    assert_eq!(links.len(), 1);
+    assert_eq!(
+        fs::read_link(&links[0])?,
+        PathBuf::from("../../usr/bin/rpm-builder.debug")
+    );
+    assert_eq!(fs::read(&links[0])?, debug);
+
+    std::fs::remove_dir_all(tmp_dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_build_id_links() -> Result<(), Box<dyn std::error::Error>> {
+    let mut tmp_dir = env::temp_dir();
+    tmp_dir.push("rpm-builder-test-build-id-links");
+    fs::create_dir_all(&tmp_dir)?;
+    let out_file = tmp_dir.join("test.rpm");
+
+    let workspace_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let rpm_builder_path = workspace_path.join("target/debug/rpm-builder");
+    let exec_file = |dest: &str| format!("{}:{}", rpm_builder_path.to_string_lossy(), dest);
+    let build = |args: Vec<&str>| {
+        Command::new(&rpm_builder_path)
+            .args(args)
+            .args(vec!["rpm-builder", "-o", &out_file.to_string_lossy()])
+            .output()
+    };
+    let query = || -> Result<String, Box<dyn std::error::Error>> {
+        let output = Command::new(&rpm_builder_path)
+            .arg("query")
+            .arg(&out_file)
+            .output()?;
+        assert!(output.status.success());
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    };
+
+    let output = build(vec!["--exec-file", &exec_file("/usr/bin/rpm-builder")])?;
+    assert!(output.status.success());
+    let stdout = query()?;
+    let link = stdout
+        .lines()
+        .find(|line| line.contains("/usr/lib/.build-id/"))
+        .expect("no build-id link");
+    assert!(link.starts_with("    lrwxrwxrwx"));
+    assert!(link.ends_with(" -> ../../../../usr/bin/rpm-builder"));
+
+    let output = build(vec![
+        "--no-build-id-links",
+        "--exec-file",
+        &exec_file("/usr/bin/rpm-builder"),
+    ])?;
+    assert!(output.status.success());
+    assert!(!query()?.contains("/usr/lib/.build-id/"));
+
+    let output = build(vec![
+        "--exec-file",
+        &exec_file("/usr/bin/rpm-builder"),
+        "--exec-file",
+        &exec_file("/usr/bin/rpm-builder-copy"),
+    ])?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("/usr/bin/rpm-builder and /usr/bin/rpm-builder-copy have the same build-id"));
+
+    // the same binary in two packages is only linked in the first one, otherwise they conflict
+    let output = Command::new(&rpm_builder_path)
+        .args(vec![
+            "--exec-file",
+            &exec_file("/usr/bin/rpm-builder"),
+            "--subpackage",
+            "tools",
+            "--exec-file",
+            &exec_file("/usr/libexec/rpm-builder"),
+            "rpm-builder",
+            "-o",
+            &tmp_dir.join("{name}.rpm").to_string_lossy(),
+        ])
+        .output()?;
+    assert!(output.status.success());
+    let links = |name: &str| -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let output = Command::new(&rpm_builder_path)
+            .arg("query")
+            .arg(tmp_dir.join(name))
+            .output()?;
+        assert!(output.status.success());
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter(|line| line.contains("/usr/lib/.build-id/"))
+            .map(|line| line.to_string())
+            .collect())
+    };
+    let main_links = links("rpm-builder.rpm")?;
+    assert_eq!(main_links.len(), 1);
+    assert!(main_links[0].ends_with(" -> ../../../../usr/bin/rpm-builder"));
+    assert!(links("rpm-builder-tools.rpm")?.is_empty());
+
+    std::fs::remove_dir_all(tmp_dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_srpm() -> Result<(), Box<dyn std::error::Error>> {
+    let mut tmp_dir = env::temp_dir();
+    tmp_dir.push("rpm-builder-test-srpm");
+    let _ = fs::remove_dir_all(&tmp_dir);
+    fs::create_dir_all(&tmp_dir)?;
+
+    let workspace_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let rpm_builder_path = workspace_path.join("target/debug/rpm-builder");
+    let readme = workspace_path.join("README.md");
+    let output = Command::new(&rpm_builder_path)
+        .args(vec![
+            "--version",
+            "1.0.0",
+            "--arch",
+            "noarch",
+            "--summary",
+            "an awesome app",
+            "--doc-file",
+            &format!("{}:/usr/share/doc/awesome/README.md", readme.display()),
+            "--requires",
+            "bash >= 4",
+            "--changelog",
+            "me:first release:2020-01-01",
+            "--source",
+            &workspace_path.join("Cargo.toml").to_string_lossy(),
+            "--srpm",
+            "--out-dir",
+            &tmp_dir.to_string_lossy(),
+            "awesome",
+        ])
+        .output()?;
+    assert!(output.status.success());
+
+    let query = |file: &str| -> Result<String, Box<dyn std::error::Error>> {
+        let output = Command::new(&rpm_builder_path)
+            .arg("query")
+            .arg(tmp_dir.join(file))
+            .output()?;
+        assert!(output.status.success());
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    };
+    let stdout = query("awesome-1.0.0-1.noarch.rpm")?;
+    assert!(stdout.contains("Source RPM  : awesome-1.0.0-1.src.rpm\n"));
+    let stdout = query("awesome-1.0.0-1.src.rpm")?;
+    assert!(stdout.contains(" Cargo.toml\n"));
+    assert!(stdout.contains(" README.md\n"));
+    assert!(stdout.contains(" awesome.spec (specfile)\n"));
+    assert!(!stdout.contains("Source RPM"));
+
+    let extract_dir = tmp_dir.join("extract");
+    let output = Command::new(&rpm_builder_path)
+        .arg("extract")
+        .arg("-C")
+        .arg(&extract_dir)
+        .arg(tmp_dir.join("awesome-1.0.0-1.src.rpm"))
+        .output()?;
+    assert!(output.status.success());
+    let spec = fs::read_to_string(extract_dir.join("awesome.spec"))?;
+    assert!(spec.contains("\nName:           awesome\n"));
+    assert!(spec.contains("\nSummary:        an awesome app\n"));
+    assert!(spec.contains("\nBuildArch:      noarch\n"));
+    assert!(spec.contains("\nSource0:        Cargo.toml\nSource1:        README.md\n"));
+    assert!(spec.contains(" %{SOURCE1} '%{buildroot}/usr/share/doc/awesome/README.md'\n"));
+    assert!(spec.contains("\nRequires:       bash >= 4\n"));
+    assert!(spec.lines().any(|line| line.starts_with("%doc %attr(")
+        && line.ends_with(", root, root) \"/usr/share/doc/awesome/README.md\"")));
+    assert!(spec.contains("\n%changelog\n* Wed Jan 01 2020 me\n- first release\n"));
+
+    std::fs::remove_dir_all(tmp_dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_emit_spec() -> Result<(), Box<dyn std::error::Error>> {
+    let mut tmp_dir = env::temp_dir();
+    tmp_dir.push("rpm-builder-test-emit-spec");
+    let _ = fs::remove_dir_all(&tmp_dir);
+    fs::create_dir_all(&tmp_dir)?;
+    let scriptlet = tmp_dir.join("post.sh");
+    fs::write(&scriptlet, "echo 100% done\n")?;
+
+    let workspace_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let rpm_builder_path = workspace_path.join("target/debug/rpm-builder");
+    let cargo_toml = workspace_path.join("Cargo.toml");
+    let output = Command::new(&rpm_builder_path)
+        .args(vec![
+            "--emit-spec",
+            "--version",
+            "1.0.0",
+            "--epoch",
+            "2",
+            "--exec-file",
+            &format!("{}:/usr/bin/awesome", cargo_toml.display()),
+            "--config-file",
+            &format!("{}:/etc/awesome.toml", cargo_toml.display()),
+            "--post-install-script",
+            &scriptlet.to_string_lossy(),
+            "--subpackage",
+            "doc",
+            "--requires",
+            "awesome = 1.0.0",
+            "--doc-file",
+            &format!("{}:/usr/share/doc/awesome/Cargo.toml", cargo_toml.display()),
+            "--out-dir",
+            &tmp_dir.to_string_lossy(),
+            "awesome",
+        ])
+        .output()?;
+    assert!(output.status.success());
+    // only the spec is printed, nothing is built
+    assert_eq!(fs::read_dir(&tmp_dir)?.count(), 1);
+
+    let spec = String::from_utf8_lossy(&output.stdout);
+    assert!(spec.contains("\nName:           awesome\nEpoch:          2\n"));
+    assert!(spec.contains("\n%package doc\n"));
+    assert!(spec.contains("\nRequires:       awesome = 1.0.0\n\n%description doc\n"));
+    assert!(spec.contains("\nSource0:        Cargo.toml\n"));
+    assert!(spec.contains("\ninstall -D -p -m 0755 %{SOURCE0} '%{buildroot}/usr/bin/awesome'\n"));
+    assert!(spec.contains("\n%post\necho 100%% done\n"));
+    assert!(spec.contains("\n%attr(0755, root, root) \"/usr/bin/awesome\"\n%config %attr("));
+    assert!(spec.contains("\n%files doc\n%doc %attr("));
+
+    std::fs::remove_dir_all(tmp_dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_extract_through_symlink() -> Result<(), Box<dyn std::error::Error>> {
+    let mut tmp_dir = env::temp_dir();
+    tmp_dir.push("rpm-builder-test-extract-through-symlink");
+    let _ = fs::remove_dir_all(&tmp_dir);
+    let outside = tmp_dir.join("outside");
+    fs::create_dir_all(&outside)?;
+    let content = tmp_dir.join("content");
+    fs::write(&content, "evil")?;
+
+    let workspace_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let rpm_builder_path = workspace_path.join("target/debug/rpm-builder");
+    let extract = |package: &PathBuf, dir: &PathBuf| {
+        Command::new(&rpm_builder_path)
+            .arg("extract")
+            .arg(package)
+            .arg("-C")
+            .arg(dir)
+            .output()
+    };
+
+    // a symlink in the payload followed by a file below it
+    let package = tmp_dir.join("symlink-in-payload.rpm");
+    let pkg = rpm::RPMBuilder::new("evil", "1.0.0", "MIT", "noarch", "evil")
+        .with_file(
+            &content,
+            rpm::RPMFileOptions::new("/x/a")
+                .symlink(outside.to_string_lossy())
+                .mode(0o120777),
+        )?
+        .with_file(&content, rpm::RPMFileOptions::new("/x/a/evil"))?
+        .build()?;
+    pkg.write(&mut fs::File::create(&package)?)?;
+    let output = extract(&package, &tmp_dir.join("extract-payload"))?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("refusing to extract /x/a/evil"));
+    assert!(!outside.join("evil").exists());
+
+    // a symlink that was already in the target directory
+    let package = tmp_dir.join("symlink-on-disk.rpm");
+    let pkg = rpm::RPMBuilder::new("evil", "1.0.0", "MIT", "noarch", "evil")
+        .with_file(&content, rpm::RPMFileOptions::new("/y/evil"))?
+        .build()?;
+    pkg.write(&mut fs::File::create(&package)?)?;
+    let extract_dir = tmp_dir.join("extract-disk");
+    fs::create_dir_all(&extract_dir)?;
+    std::os::unix::fs::symlink(&outside, extract_dir.join("y"))?;
+    let output = extract(&package, &extract_dir)?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("refusing to extract /y/evil"));
+    assert!(!outside.join("evil").exists());
+
+    std::fs::remove_dir_all(tmp_dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_verify_tampered_header() -> Result<(), Box<dyn std::error::Error>> {
+    let mut tmp_dir = env::temp_dir();
+    tmp_dir.push("rpm-builder-test-verify-tampered-header");
+    fs::create_dir_all(&tmp_dir)?;
+    let out_file = tmp_dir.join("test.rpm");
+    let tampered_file = tmp_dir.join("tampered.rpm");
+
+    let workspace_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let rpm_builder_path = workspace_path.join("target/debug/rpm-builder");
+    let output = Command::new(&rpm_builder_path)
+        .args(vec![
+            "--file",
+            &format!(
+                "{}/Cargo.toml:/usr/share/rpm-builder/Cargo.toml",
+                workspace_path.to_string_lossy()
+            ),
+            "--license",
+            "MIT",
+            "rpm-builder",
+            "-o",
+            &out_file.to_string_lossy(),
+        ])
+        .output()?;
+    assert!(output.status.success());
+    let verify = || {
+        Command::new(&rpm_builder_path)
+            .arg("verify")
+            .arg(&tampered_file)
+            .output()
+    };
+
+    // the signature header follows the 96 byte lead and is padded to 8 bytes
+    let raw = fs::read(&out_file)?;
+    let count = u32::from_be_bytes([raw[104], raw[105], raw[106], raw[107]]) as usize;
+    let size = u32::from_be_bytes([raw[108], raw[109], raw[110], raw[111]]) as usize;
+    let header_start = 96 + (16 + 16 * count + size).div_ceil(8) * 8;
+
+    let mut stripped = raw[..96].to_vec();
+    stripped.extend_from_slice(&[0x8e, 0xad, 0xe8, 0x01, 0, 0, 0, 0]);
+    stripped.extend_from_slice(&[0; 8]);
+    stripped.extend_from_slice(&raw[header_start..]);
+    fs::write(&tampered_file, stripped)?;
+    let output = verify()?;
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("header digest: FAILED (the package has no header digest)"));
+    assert!(stdout.contains("MD5 digest: not present"));
+
+    let mut modified = raw.clone();
+    let license = header_start
+        + raw[header_start..]
+            .windows(4)
+            .position(|window| window == b"MIT\0")
+            .unwrap();
+    modified[license..license + 3].copy_from_slice(b"BSD");
+    fs::write(&tampered_file, modified)?;
+    let output = verify()?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("header SHA256 digest: FAILED"));
+
+    std::fs::remove_dir_all(tmp_dir)?;
+    Ok(())
+}